@@ -0,0 +1,368 @@
+//! Support for [asciicast v2] recordings, as produced by asciinema: a JSON header followed by one
+//! JSON array per line for every timed event.
+//!
+//! A recording can be rendered as a single frame, such as the final frame, or as an animation of
+//! every frame using CSS keyframes, requiring no JavaScript.
+//!
+//! [asciicast v2]: https://docs.asciinema.org/manual/asciicast/v2/
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+
+use serde::Deserialize;
+
+use crate::parser::split_sequence;
+use crate::terminal::Terminal;
+use crate::writer::render_blocks;
+use crate::{number, RenderOptions};
+
+/// How long the final frame of an animation is shown for before the animation repeats, in seconds
+/// of playback.
+const FINAL_FRAME_DURATION: f64 = 2.0;
+
+/// An error encountered while parsing a [`Recording`].
+#[derive(Debug)]
+pub enum CastError {
+    /// The recording is empty.
+    MissingHeader,
+    /// The header or an event is not valid JSON in the expected format.
+    Json(serde_json::Error),
+    /// The recording is not an asciicast v2 recording.
+    UnsupportedVersion(u64),
+}
+
+impl core::fmt::Display for CastError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CastError::MissingHeader => write!(f, "recording has no header"),
+            CastError::Json(e) => write!(f, "invalid recording: {e}"),
+            CastError::UnsupportedVersion(version) => {
+                write!(f, "unsupported asciicast version, expected 2: {version}")
+            }
+        }
+    }
+}
+
+impl core::error::Error for CastError {
+    // Without `std`, older versions of `serde_json` don't implement `core::error::Error`
+    #[cfg(feature = "std")]
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            CastError::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for CastError {
+    fn from(error: serde_json::Error) -> Self {
+        CastError::Json(error)
+    }
+}
+
+/// The header of a recording. Fields not used for rendering are ignored.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Header {
+    /// The version of the asciicast format, which must be 2.
+    pub version: u64,
+    /// The width of the terminal the recording was made in, in columns.
+    pub width: usize,
+    /// The height of the terminal the recording was made in, in lines.
+    pub height: usize,
+    /// The title of the recording.
+    pub title: Option<String>,
+    /// The longest pause between events during playback, in seconds.
+    pub idle_time_limit: Option<f64>,
+}
+
+/// Output written to the terminal at a time relative to the start of the recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// The time the output was written, in seconds.
+    pub time: f64,
+    /// The output, which may end with an incomplete escape sequence that is completed by the
+    /// next event.
+    pub data: String,
+}
+
+/// A parsed asciicast v2 recording. Only output events are kept; input, marker, and resize events
+/// are ignored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    pub header: Header,
+    pub events: Vec<Event>,
+}
+
+/// Options for rendering a [`Recording`] as an animation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationOptions {
+    /// How each frame is rendered. [`RenderOptions::emulate_terminal`],
+    /// [`RenderOptions::terminal_width`], and [`RenderOptions::terminal_height`] are ignored, as
+    /// frames always use a terminal the size of the recording.
+    pub render: RenderOptions,
+    /// The playback speed, where 2.0 plays the recording twice as fast.
+    pub speed: f64,
+    /// The shortest time between frames, in seconds of the recording. Events written within this
+    /// time of the start of a frame are shown as part of that frame, limiting the size of the
+    /// generated HTML.
+    pub frame_time: f64,
+    /// The `id` of the generated element, which prefixes the names of the generated keyframes
+    /// and must be unique within a page.
+    pub id: String,
+}
+
+impl Default for AnimationOptions {
+    fn default() -> Self {
+        AnimationOptions {
+            render: RenderOptions::default(),
+            speed: 1.0,
+            frame_time: 0.1,
+            id: "ansi-cast".to_string(),
+        }
+    }
+}
+
+impl Recording {
+    /// Parse an asciicast v2 recording.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error when the header or any event can't be parsed, or the recording uses
+    /// another version of the asciicast format.
+    pub fn parse(input: &str) -> Result<Self, CastError> {
+        let mut lines = input.lines().filter(|line| !line.trim().is_empty());
+        let header: Header = serde_json::from_str(lines.next().ok_or(CastError::MissingHeader)?)?;
+        if header.version != 2 {
+            return Err(CastError::UnsupportedVersion(header.version));
+        }
+
+        let mut events = vec![];
+        for line in lines {
+            let (time, kind, data): (f64, String, String) = serde_json::from_str(line)?;
+            if kind == "o" {
+                events.push(Event { time, data });
+            }
+        }
+
+        Ok(Recording { header, events })
+    }
+
+    /// Returns the output written up to and including the given time, in seconds.
+    fn output_until(&self, time: f64) -> String {
+        self.events
+            .iter()
+            .take_while(|event| event.time <= time)
+            .map(|event| event.data.as_str())
+            .collect()
+    }
+
+    /// Render the contents of the terminal at the given time, in seconds, to HTML. Use
+    /// [`f64::INFINITY`] to render the final frame. Like
+    /// [`RenderOptions::emulate_terminal`], every line that was written is kept as scrollback,
+    /// rather than only the lines that fit within the height of the recording.
+    #[must_use]
+    pub fn render_frame(&self, time: f64, options: &RenderOptions) -> String {
+        let options = RenderOptions {
+            emulate_terminal: true,
+            terminal_width: options.terminal_width.or(Some(self.header.width)),
+            terminal_height: options.terminal_height.or(Some(self.header.height)),
+            ..options.clone()
+        };
+        crate::rewrite_ansi_to_html_with(self.output_until(time).as_str(), &options)
+    }
+
+    /// Returns every event alongside the time it is played at, in seconds, after limiting pauses
+    /// to the idle time limit of the recording and applying the playback speed.
+    fn playback_times(&self, speed: f64) -> Vec<(f64, &Event)> {
+        let mut elapsed = 0.0;
+        let mut previous = 0.0;
+        let mut times = vec![];
+        for event in &self.events {
+            let mut pause = (event.time - previous).max(0.0);
+            if let Some(limit) = self.header.idle_time_limit {
+                pause = pause.min(limit);
+            }
+            elapsed += pause;
+            previous = event.time;
+            times.push((elapsed / speed, event));
+        }
+        times
+    }
+
+    /// Render the recording as an animation of every frame, using CSS keyframes to show each
+    /// frame in turn. Frames are the size of the recording's terminal, and the animation repeats
+    /// after showing the final frame. When the reader prefers reduced motion, only the final
+    /// frame is shown.
+    #[must_use]
+    pub fn render_animation(&self, options: &AnimationOptions) -> String {
+        let speed = if options.speed > 0.0 {
+            options.speed
+        } else {
+            1.0
+        };
+        let frame_time = options.frame_time / speed;
+
+        // Every frame is the time it is shown at and its rendered contents
+        let mut frames: Vec<(f64, String)> = vec![];
+        let mut terminal = Terminal::new(Some(self.header.width), Some(self.header.height));
+        let mut pending = String::new();
+        let mut frame_start = 0.0;
+        for (time, event) in self.playback_times(speed) {
+            if time - frame_start >= frame_time {
+                let blocks = terminal.screen(self.header.height);
+                frames.push((
+                    frame_start,
+                    render_blocks(
+                        blocks.iter().map(|(style, text)| (style, text.as_str())),
+                        &options.render,
+                    ),
+                ));
+                frame_start = time;
+            }
+
+            // Escape sequences may be split between events
+            pending.push_str(&event.data);
+            let mut input = pending.as_str();
+            while let Some((sequence, rest)) = split_sequence(input) {
+                terminal.feed(&sequence);
+                input = rest;
+            }
+            pending = input.to_string();
+            terminal.take_final_lines();
+        }
+        let blocks = terminal.screen(self.header.height);
+        frames.push((
+            frame_start,
+            render_blocks(
+                blocks.iter().map(|(style, text)| (style, text.as_str())),
+                &options.render,
+            ),
+        ));
+
+        let duration = frame_start + FINAL_FRAME_DURATION / speed;
+        let id = html_escape::encode_double_quoted_attribute(&options.id);
+        let mut rules = vec![];
+        for (i, (start, _)) in frames.iter().enumerate() {
+            let start = start / duration * 100.0;
+            let end = frames
+                .get(i + 1)
+                .map_or(100.0, |(next, _)| next / duration * 100.0);
+            let hidden = if end < 100.0 { "hidden" } else { "visible" };
+            rules.push(format!(
+                "@keyframes {id}-{i} {{ {}% {{ visibility: visible; }} {}% {{ visibility: {hidden}; }} }}",
+                number(start),
+                number(end),
+            ));
+        }
+        rules.push(format!("#{id} {{ display: grid; }}"));
+        rules.push(format!(
+            "#{id} > .ansi_output {{ grid-area: 1 / 1; visibility: hidden; \
+             animation-duration: {}s; animation-timing-function: step-end; \
+             animation-iteration-count: infinite; }}",
+            number(duration),
+        ));
+        rules.push(format!(
+            "@media (prefers-reduced-motion: reduce) {{ \
+             #{id} > .ansi_output {{ animation: none; }} \
+             #{id} > .ansi_output:last-child {{ visibility: visible; }} }}"
+        ));
+
+        let mut output = vec![
+            format!("<div class=\"ansi_cast\" id=\"{id}\">"),
+            format!("<style>\n{}\n</style>", rules.join("\n")),
+        ];
+        for (i, (_, frame)) in frames.iter().enumerate() {
+            output.push(format!(
+                "<pre class=\"ansi_output\" style=\"animation-name: {id}-{i}\"><code>{frame}</code></pre>"
+            ));
+        }
+        output.push("</div>".to_string());
+        output.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static RECORDING: &str = r#"{"version": 2, "width": 10, "height": 2, "title": "demo", "idle_time_limit": 1.0}
+[0.5, "o", "\u001b[32mone\u001b[0m\r\n"]
+[0.55, "i", "x"]
+[0.6, "o", "two\r\n\u001b["]
+[5.0, "o", "1mthree"]
+"#;
+
+    #[test]
+    fn parsing_recordings() {
+        let recording = Recording::parse(RECORDING).unwrap();
+        assert_eq!(recording.header.width, 10);
+        assert_eq!(recording.header.title.as_deref(), Some("demo"));
+        assert_eq!(recording.events.len(), 3);
+        assert_eq!(recording.events[2].data, "1mthree");
+
+        assert!(matches!(
+            Recording::parse(r#"{"version": 1, "width": 1, "height": 1}"#),
+            Err(CastError::UnsupportedVersion(1))
+        ));
+        assert!(matches!(
+            Recording::parse(""),
+            Err(CastError::MissingHeader)
+        ));
+        assert!(matches!(
+            Recording::parse("{\"version\": 2, \"width\": 1, \"height\": 1}\n[0.1, \"o\"]"),
+            Err(CastError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn rendering_frames() {
+        let recording = Recording::parse(RECORDING).unwrap();
+        let options = RenderOptions {
+            compact: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            recording.render_frame(0.5, &options),
+            "<pre class=\"ansi_output\"><code>\
+             <span style=\"color: var(--color-green)\">one\n</span>\
+             </code></pre>"
+        );
+        assert_eq!(
+            recording.render_frame(f64::INFINITY, &options),
+            "<pre class=\"ansi_output\"><code>\
+             <span style=\"color: var(--color-green)\">one</span>\ntwo\n\
+             <span style=\"font-weight: bold\">three</span>\
+             </code></pre>"
+        );
+    }
+
+    #[test]
+    fn rendering_animations() {
+        let recording = Recording::parse(RECORDING).unwrap();
+        let options = AnimationOptions {
+            speed: 2.0,
+            id: "cast".to_string(),
+            ..Default::default()
+        };
+        let html = recording.render_animation(&options);
+
+        // Pauses are limited to a second, then halved by the playback speed
+        assert!(html.contains("animation-duration: 1.8s;"));
+        assert!(html.contains(
+            "@keyframes cast-0 { 0% { visibility: visible; } 13.89% { visibility: hidden; } }"
+        ));
+        assert!(html.contains(
+            "@keyframes cast-2 { 44.44% { visibility: visible; } 100% { visibility: visible; } }"
+        ));
+        assert!(html.contains(
+            "<pre class=\"ansi_output\" style=\"animation-name: cast-0\"><code></code></pre>"
+        ));
+        // Only the height of the recording is shown in each frame
+        assert!(html.contains(
+            "<pre class=\"ansi_output\" style=\"animation-name: cast-2\"><code>two\n\
+             <strong>three</strong></code></pre>"
+        ));
+        assert_eq!(html.matches("<pre").count(), 3);
+    }
+}
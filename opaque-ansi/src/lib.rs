@@ -1,37 +1,170 @@
-use ansi_parser::{AnsiParser, AnsiSequence, Output};
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+use alloc::borrow::Cow;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::ops::RangeInclusive;
+
+pub mod asciicast;
+pub mod palette;
+mod parser;
+pub mod render;
+mod svg;
+mod terminal;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+mod writer;
+
+pub use render::Renderer;
+pub use svg::{rewrite_ansi_to_svg, SvgOptions};
+#[cfg(feature = "std")]
+pub use writer::AnsiHtmlWriter;
 
 #[cfg(feature = "tracing")]
 use tracing::debug;
 
-/// The three color options for SGR color codes and a default option.
-#[derive(Default, Debug, Clone, PartialEq, Eq)]
-enum SgrColor {
+/// The four color options for SGR color codes and a default option.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SgrColor {
+    /// The default color of the terminal.
     #[default]
     Reset,
     /// A value of 0..=7 to represent one of 8 standard terminal colors.
     Console(u8),
+    /// A value of 0..=7 to represent one of 8 bright (high-intensity) terminal colors.
+    Bright(u8),
     /// A value of 0..=255 to represent one of 256 standard terminal colors.
     ExpandedConsole(u8),
     /// Three values of 0..=255 to represent a "true" RGB color value.
     True(u8, u8, u8),
 }
 
+impl SgrColor {
+    /// Returns the CSS value representing the color, or `None` for the default color. All
+    /// non-truecolor colors require a stylesheet to be provided.
+    /// [`SgrColor::Console`] values are represented using a `--color-{c}` CSS variable, using
+    /// the value from [`COLORS`].
+    /// [`SgrColor::Bright`] values are represented using a `--color-bright-{c}` CSS variable,
+    /// using the value from [`COLORS`].
+    /// [`SgrColor::ExpandedConsole`] values are represented using a `--terminal-color-{n}` CSS
+    /// variable.
+    /// [`SgrColor::True`] values are represented using `rgb({r}, {g}, {b})`.
+    fn css_value(&self) -> Option<String> {
+        match self {
+            SgrColor::Console(n @ 0..=7) => Some(format!("var(--color-{})", COLORS[*n as usize])),
+            SgrColor::Bright(n @ 0..=7) => {
+                Some(format!("var(--color-bright-{})", COLORS[*n as usize]))
+            }
+            SgrColor::ExpandedConsole(n) => Some(format!("var(--terminal-color-{n})")),
+            SgrColor::True(r, g, b) => Some(format!("rgb({r}, {g}, {b})")),
+            _ => None,
+        }
+    }
+
+    /// Returns the class representing the color, or `None` for the default color. The `kind` is
+    /// `fg`, `bg`, or `ul`, depending on whether the color is for the foreground, background, or
+    /// underline.
+    /// [`SgrColor::Console`], [`SgrColor::Bright`], and [`SgrColor::ExpandedConsole`] values are
+    /// represented using their index within the 256 standard terminal colors, such as
+    /// `{prefix}fg-{n}`.
+    /// [`SgrColor::True`] values are represented using a hex value, such as
+    /// `{prefix}fg-rgb-{rrggbb}`.
+    fn class_name(&self, prefix: &str, kind: &str) -> Option<String> {
+        match self {
+            SgrColor::Console(n) | SgrColor::ExpandedConsole(n) => {
+                Some(format!("{prefix}{kind}-{n}"))
+            }
+            SgrColor::Bright(n) => Some(format!("{prefix}{kind}-{}", n + 8)),
+            SgrColor::True(r, g, b) => Some(format!("{prefix}{kind}-rgb-{r:02x}{g:02x}{b:02x}")),
+            SgrColor::Reset => None,
+        }
+    }
+
+    /// Returns the RGB value of the color within the given palette, or `None` for the default
+    /// color. [`SgrColor::Bright`] values use colors 8..=15 of the palette.
+    #[must_use]
+    pub fn rgb(&self, palette: &palette::Palette) -> Option<palette::Rgb> {
+        match self {
+            SgrColor::Console(n) | SgrColor::ExpandedConsole(n) => Some(palette.color(*n)),
+            SgrColor::Bright(n) => Some(palette.color(n + 8)),
+            SgrColor::True(r, g, b) => Some(palette::Rgb(*r, *g, *b)),
+            SgrColor::Reset => None,
+        }
+    }
+}
+
+/// The style of an underline, set by the subparameter of SGR 4, such as `4:3` for a curly underline.
+/// Double underlines are represented by [`Style::double_underline`] instead.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnderlineStyle {
+    /// A single straight line, set by `4` or `4:1`.
+    #[default]
+    Straight,
+    /// A wavy line, set by `4:3`.
+    Curly,
+    /// A dotted line, set by `4:4`.
+    Dotted,
+    /// A dashed line, set by `4:5`.
+    Dashed,
+}
+
+impl UnderlineStyle {
+    /// Returns the value of `text-decoration-style` for the underline, or `None` for the default
+    /// straight line.
+    fn css_value(self) -> Option<&'static str> {
+        match self {
+            UnderlineStyle::Straight => None,
+            UnderlineStyle::Curly => Some("wavy"),
+            UnderlineStyle::Dotted => Some("dotted"),
+            UnderlineStyle::Dashed => Some("dashed"),
+        }
+    }
+}
+
 /// The continuous state of the next block of text. A new set of SGR parameters does not imply that
 /// the state should be reset unless the 0 (reset) parameter has been explicitly used.
-#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash)]
 #[allow(clippy::struct_excessive_bools)]
-struct GraphicsModeState {
+pub struct Style {
     // reset is interpreted as resetting everything to default
     // the methods defined here are taken from:
     // https://en.wikipedia.org/wiki/ANSI_escape_code#SGR_(Select_Graphic_Rendition)_parameters
     // and have been selected in accordance with their compatibility with static HTML
-    bold: bool,
-    italic: bool,
-    underline: bool,
-    strikethrough: bool,
+    /// Bold or increased intensity, set by SGR 1.
+    pub bold: bool,
+    /// Faint or decreased intensity, set by SGR 2.
+    pub dim: bool,
+    /// Italic, set by SGR 3.
+    pub italic: bool,
+    /// Underlined, set by SGR 4.
+    pub underline: bool,
+    /// The style of the underline when [`Style::underline`] is set, set by SGR 4 with a
+    /// subparameter.
+    pub underline_style: UnderlineStyle,
+    /// Doubly underlined, set by SGR 21 or `4:2`.
+    pub double_underline: bool,
+    /// Foreground and background colors swapped, set by SGR 7.
+    pub inverse: bool,
+    /// Concealed, set by SGR 8.
+    pub hidden: bool,
+    /// Crossed out, set by SGR 9.
+    pub strikethrough: bool,
+    /// Overlined, set by SGR 53.
+    pub overline: bool,
+
+    /// The foreground color, before being swapped by [`Style::inverse`].
+    pub color: SgrColor,
+    /// The background color, before being swapped by [`Style::inverse`].
+    pub background_color: SgrColor,
+    /// The color of underlines, set by SGR 58. The default color is the color of the text.
+    pub underline_color: SgrColor,
 
-    color: SgrColor,
-    background_color: SgrColor,
+    // hyperlinks are set by OSC 8 rather than SGR parameters, and are kept after a reset
+    /// The target of the hyperlink the text is part of, set by OSC 8.
+    pub hyperlink: Option<String>,
 }
 
 /// The standard terminal colors, approximated from experience with various terminals.
@@ -39,6 +172,12 @@ static COLORS: [&str; 8] = [
     "black", "red", "green", "yellow", "blue", "purple", "cyan", "gray",
 ];
 
+/// The color used in place of a default foreground color when colors are inverted.
+static DEFAULT_FOREGROUND: &str = "var(--color-text)";
+
+/// The color used in place of a default background color when colors are inverted.
+static DEFAULT_BACKGROUND: &str = "var(--color-background)";
+
 /// Iterate over a slice using a variable-argument matching pattern. The iterator moves after the
 /// end of the slice, so matching two values moves the slice to the right by two.
 ///
@@ -71,38 +210,272 @@ macro_rules! iter_over {
     }
 }
 
-impl GraphicsModeState {
-    /// Returns a copy of Self, setting values based off SGR parameters from the input.
+/// Returns a truecolor color, or `None` if any of the values are out of range.
+fn true_color(r: u16, g: u16, b: u16) -> Option<SgrColor> {
+    Some(SgrColor::True(
+        r.try_into().ok()?,
+        g.try_into().ok()?,
+        b.try_into().ok()?,
+    ))
+}
+
+impl Style {
+    /// Returns a copy of Self, setting values based off SGR parameters from the input, which may
+    /// not have subparameters.
+    #[cfg(test)]
     fn clone_from_scan(&self, input: &[u8]) -> Self {
+        let params: Vec<_> = input.iter().map(|n| vec![Some(u16::from(*n))]).collect();
+        self.clone_from_params(&params)
+    }
+
+    /// Returns a copy of Self, setting values based off SGR parameters and their subparameters.
+    ///
+    /// Extended colors given as separate parameters, such as `38;5;n`, take the parameters after
+    /// them. When those parameters are missing or out of range, the rest of the sequence is
+    /// ignored, since where the color ends can't be known. Unknown parameters, and extended colors
+    /// given as subparameters that are missing or out of range, are ignored on their own.
+    fn clone_from_params(&self, params: &[parser::SgrParam]) -> Self {
         let mut state = self.clone();
 
-        iter_over! {
-            input;
-            [0] => state = GraphicsModeState::default(),
-            [1] => state.bold = true,
-            [3] => state.italic = true,
-            [4] => state.underline = true,
-            [9] => state.strikethrough = true,
-            [n @ 30..=37] => state.color = SgrColor::Console(n - 30),
-            [n @ 40..=47] => state.background_color = SgrColor::Console(n - 40),
-            [38, 5, n] => state.color = SgrColor::ExpandedConsole(*n),
-            [48, 5, n] => state.background_color = SgrColor::ExpandedConsole(*n),
-            [38, 2, r, g, b] => state.color = SgrColor::True(*r, *g, *b),
-            [48, 2, r, g, b] => state.background_color = SgrColor::True(*r, *g, *b),
-            [39] => state.color = SgrColor::Reset,
-            [49] => state.background_color = SgrColor::Reset,
+        let params: Vec<&[Option<u16>]> = params.iter().map(Vec::as_slice).collect();
+        let mut input = &params[..];
+        while let [param, rest @ ..] = input {
+            input = rest;
+            match param {
+                [Some(kind @ (38 | 48 | 58))] => {
+                    let (color, rest) = match input {
+                        [[Some(5)], [Some(n)], rest @ ..] => {
+                            (u8::try_from(*n).ok().map(SgrColor::ExpandedConsole), rest)
+                        }
+                        [[Some(2)], [Some(r)], [Some(g)], [Some(b)], rest @ ..] => {
+                            (true_color(*r, *g, *b), rest)
+                        }
+                        _ => (None, input),
+                    };
+                    let Some(color) = color else {
+                        break;
+                    };
+                    state.set_extended_color(*kind, color);
+                    input = rest;
+                }
+                [Some(kind @ (38 | 48 | 58)), subparams @ ..] => {
+                    let color = match subparams {
+                        [Some(5), Some(n)] => u8::try_from(*n).ok().map(SgrColor::ExpandedConsole),
+                        // The color space is meant to come first, but is often left out entirely
+                        [Some(2), _, Some(r), Some(g), Some(b)]
+                        | [Some(2), Some(r), Some(g), Some(b)] => true_color(*r, *g, *b),
+                        _ => None,
+                    };
+                    if let Some(color) = color {
+                        state.set_extended_color(*kind, color);
+                    }
+                }
+                [Some(4), Some(style)] => {
+                    let (underline, double_underline, underline_style) = match style {
+                        0 => (false, false, UnderlineStyle::Straight),
+                        1 => (true, false, UnderlineStyle::Straight),
+                        2 => (false, true, UnderlineStyle::Straight),
+                        3 => (true, false, UnderlineStyle::Curly),
+                        4 => (true, false, UnderlineStyle::Dotted),
+                        5 => (true, false, UnderlineStyle::Dashed),
+                        _ => continue,
+                    };
+                    state.underline = underline;
+                    state.double_underline = double_underline;
+                    state.underline_style = underline_style;
+                }
+                [Some(n)] => state.apply_param(*n),
+                _ => (),
+            }
         }
 
         state
     }
 
+    /// Set the foreground, background, or underline color, for SGR 38, 48, and 58 respectively.
+    fn set_extended_color(&mut self, kind: u16, color: SgrColor) {
+        match kind {
+            38 => self.color = color,
+            48 => self.background_color = color,
+            _ => self.underline_color = color,
+        }
+    }
+
+    /// Set values based off a single SGR parameter without subparameters, ignoring unknown
+    /// parameters.
+    fn apply_param(&mut self, n: u16) {
+        match n {
+            0 => {
+                *self = Style {
+                    hyperlink: self.hyperlink.take(),
+                    ..Default::default()
+                };
+            }
+            1 => self.bold = true,
+            2 => self.dim = true,
+            3 => self.italic = true,
+            4 => {
+                self.underline = true;
+                self.underline_style = UnderlineStyle::Straight;
+                self.double_underline = false;
+            }
+            7 => self.inverse = true,
+            8 => self.hidden = true,
+            9 => self.strikethrough = true,
+            21 => {
+                self.underline = false;
+                self.double_underline = true;
+            }
+            22 => {
+                self.bold = false;
+                self.dim = false;
+            }
+            23 => self.italic = false,
+            24 => {
+                self.underline = false;
+                self.underline_style = UnderlineStyle::Straight;
+                self.double_underline = false;
+            }
+            27 => self.inverse = false,
+            28 => self.hidden = false,
+            29 => self.strikethrough = false,
+            53 => self.overline = true,
+            55 => self.overline = false,
+            // The ranges guarantee the values fit in a u8
+            #[allow(clippy::cast_possible_truncation)]
+            30..=37 => self.color = SgrColor::Console((n - 30) as u8),
+            #[allow(clippy::cast_possible_truncation)]
+            40..=47 => self.background_color = SgrColor::Console((n - 40) as u8),
+            #[allow(clippy::cast_possible_truncation)]
+            90..=97 => self.color = SgrColor::Bright((n - 90) as u8),
+            #[allow(clippy::cast_possible_truncation)]
+            100..=107 => self.background_color = SgrColor::Bright((n - 100) as u8),
+            39 => self.color = SgrColor::Reset,
+            49 => self.background_color = SgrColor::Reset,
+            59 => self.underline_color = SgrColor::Reset,
+            _ => (),
+        }
+    }
+
+    /// Returns the foreground and background colors of the text, swapped if the state is
+    /// inverted.
+    #[must_use]
+    pub fn resolved_colors(&self) -> (&SgrColor, &SgrColor) {
+        if self.inverse {
+            (&self.background_color, &self.color)
+        } else {
+            (&self.color, &self.background_color)
+        }
+    }
+
+    /// Returns the CSS values of the foreground and background colors, swapped when the state is
+    /// inverted. Default colors are empty strings, unless the state is inverted.
+    fn css_colors(&self) -> (String, String) {
+        if self.inverse {
+            (
+                self.background_color
+                    .css_value()
+                    .unwrap_or_else(|| DEFAULT_BACKGROUND.to_string()),
+                self.color
+                    .css_value()
+                    .unwrap_or_else(|| DEFAULT_FOREGROUND.to_string()),
+            )
+        } else {
+            (
+                self.color.css_value().unwrap_or_default(),
+                self.background_color.css_value().unwrap_or_default(),
+            )
+        }
+    }
+
+    /// Returns whether the formatting is only visible on the glyphs of the text, so that
+    /// whitespace looks the same as if it were unformatted.
+    fn is_foreground_only(&self) -> bool {
+        !self.underline
+            && !self.double_underline
+            && !self.strikethrough
+            && !self.overline
+            && !self.inverse
+            && self.background_color == SgrColor::Reset
+            && self.hyperlink.is_none()
+    }
+
+    /// Returns the CSS declarations representing the style and color of the underline, if the text
+    /// is underlined.
+    fn underline_styles(&self) -> Vec<String> {
+        let mut styles = vec![];
+        let style = if self.double_underline {
+            Some("double")
+        } else if self.underline {
+            self.underline_style.css_value()
+        } else {
+            None
+        };
+        if let Some(style) = style {
+            styles.push(format!("text-decoration-style: {style}"));
+        }
+        if self.underline || self.double_underline {
+            if let Some(color) = self.underline_color.css_value() {
+                styles.push(format!("text-decoration-color: {color}"));
+            }
+        }
+        styles
+    }
+
+    /// Returns the CSS declarations representing the formatting of the text, for use in a single
+    /// `style` attribute. See [`Style::build_tags`] for how colors are represented.
+    fn build_styles(&self) -> Vec<String> {
+        let mut styles = vec![];
+
+        if self.bold {
+            styles.push("font-weight: bold".to_string());
+        }
+
+        if self.italic {
+            styles.push("font-style: italic".to_string());
+        }
+
+        let decorations = [
+            (self.underline || self.double_underline, "underline"),
+            (self.strikethrough, "line-through"),
+            (self.overline, "overline"),
+        ];
+        let lines: Vec<&str> = decorations
+            .into_iter()
+            .filter(|(enabled, _)| *enabled)
+            .map(|(_, line)| line)
+            .collect();
+        if !lines.is_empty() {
+            styles.push(format!("text-decoration-line: {}", lines.join(" ")));
+        }
+
+        styles.extend(self.underline_styles());
+
+        if self.dim {
+            styles.push("opacity: 0.5".to_string());
+        }
+
+        if self.hidden {
+            styles.push("visibility: hidden".to_string());
+        }
+
+        let (color, background_color) = self.css_colors();
+
+        if !color.is_empty() {
+            styles.push(format!("color: {color}"));
+        }
+
+        if !background_color.is_empty() {
+            styles.push(format!("background-color: {background_color}"));
+        }
+
+        styles
+    }
+
     /// Returns opening and closing HTML tags representing the formatting of the text. All
-    /// non-truecolor colors require a stylesheet to be provided.
-    /// [`SgrColor::Console`] values are represented using a `--color-{c}` CSS variable, using
-    /// the value from [`COLORS`].
-    /// [`SgrColor::ExpandedConsole`] values are represented using a `--terminal-color-{n}` CSS
-    /// variable.
-    /// [`SgrColor::True`] values are represented using `rgb({r}, {g}, {b})`.
+    /// non-truecolor colors require a stylesheet to be provided; see [`SgrColor::css_value`].
+    /// When the state is inverted, the foreground and background colors are swapped, using the
+    /// `--color-text` and `--color-background` CSS variables in place of default colors.
     /// When the state matches the "default" state, empty strings are returned.
     fn build_tags(&self) -> (String, String) {
         if self == &Self::default() {
@@ -122,8 +495,13 @@ impl GraphicsModeState {
             closing_tags.push("</em>".to_string());
         }
 
-        if self.underline {
-            opening_tags.push("<u>".to_string());
+        if self.underline || self.double_underline {
+            let styles = self.underline_styles();
+            if styles.is_empty() {
+                opening_tags.push("<u>".to_string());
+            } else {
+                opening_tags.push(format!("<u style=\"{}\">", styles.join("; ")));
+            }
             closing_tags.push("</u>".to_string());
         }
 
@@ -132,121 +510,561 @@ impl GraphicsModeState {
             closing_tags.push("</s>".to_string());
         }
 
-        match self.color {
-            SgrColor::Console(n @ 0..=7) => {
-                let span = format!(
-                    "<span style=\"color: var(--color-{})\">",
-                    COLORS[n as usize]
-                );
-                opening_tags.push(span);
-                closing_tags.push("</span>".to_string());
+        let mut styles = vec![];
+
+        if self.overline {
+            styles.push("text-decoration: overline".to_string());
+        }
+
+        if self.dim {
+            styles.push("opacity: 0.5".to_string());
+        }
+
+        if self.hidden {
+            styles.push("visibility: hidden".to_string());
+        }
+
+        if !styles.is_empty() {
+            opening_tags.push(format!("<span style=\"{}\">", styles.join("; ")));
+            closing_tags.push("</span>".to_string());
+        }
+
+        let (color, background_color) = self.css_colors();
+
+        if !color.is_empty() {
+            opening_tags.push(format!("<span style=\"color: {color}\">"));
+            closing_tags.push("</span>".to_string());
+        }
+
+        if !background_color.is_empty() {
+            opening_tags.push(format!(
+                "<span style=\"background-color: {background_color}\">"
+            ));
+            closing_tags.push("</span>".to_string());
+        }
+
+        (
+            opening_tags.join(""),
+            closing_tags.into_iter().rev().collect::<String>(),
+        )
+    }
+
+    /// Returns an opening and closing `<span>` tag representing the formatting of the text using
+    /// classes starting with the given prefix. Colors are represented using
+    /// [`SgrColor::class_name`]. Default colors of inverted text are represented using
+    /// `{prefix}fg-inverse` and `{prefix}bg-inverse`.
+    /// When the state matches the "default" state, empty strings are returned.
+    fn build_class_tags(&self, prefix: &str) -> (String, String) {
+        let classes = self.build_classes(prefix);
+        if classes.is_empty() {
+            return (String::new(), String::new());
+        }
+
+        (
+            format!("<span class=\"{}\">", classes.join(" ")),
+            "</span>".to_string(),
+        )
+    }
+
+    /// Returns the classes representing the formatting of the text, starting with the given
+    /// prefix. See [`Style::build_class_tags`] for how colors are represented.
+    fn build_classes(&self, prefix: &str) -> Vec<String> {
+        let attributes = [
+            (self.bold, "bold"),
+            (self.dim, "dim"),
+            (self.italic, "italic"),
+            (self.underline, "underline"),
+            (self.double_underline, "double-underline"),
+            (self.strikethrough, "strikethrough"),
+            (self.overline, "overline"),
+            (self.hidden, "hidden"),
+        ];
+        let mut classes: Vec<String> = attributes
+            .into_iter()
+            .filter(|(enabled, _)| *enabled)
+            .map(|(_, name)| format!("{prefix}{name}"))
+            .collect();
+
+        if self.underline {
+            if let Some(style) = self.underline_style.css_value() {
+                classes.push(format!("{prefix}underline-{style}"));
             }
-            SgrColor::ExpandedConsole(n) => {
-                let span = format!("<span style=\"color: var(--terminal-color-{n})\">");
-                opening_tags.push(span);
-                closing_tags.push("</span>".to_string());
+        }
+        if self.underline || self.double_underline {
+            classes.extend(self.underline_color.class_name(prefix, "ul"));
+        }
+
+        let (color, background_color) = self.resolved_colors();
+        for (kind, color) in [("fg", color), ("bg", background_color)] {
+            match color.class_name(prefix, kind) {
+                Some(class) => classes.push(class),
+                None if self.inverse => classes.push(format!("{prefix}{kind}-inverse")),
+                None => (),
             }
-            SgrColor::True(r, g, b) => {
-                let span = format!("<span style=\"color: rgb({r}, {g}, {b})\">");
-                opening_tags.push(span);
-                closing_tags.push("</span>".to_string());
+        }
+
+        classes
+    }
+}
+
+/// How formatting is attached to the HTML generated from an ANSI terminal output.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub enum StyleMode {
+    /// Nested tags with inline `style` attributes referring to CSS variables.
+    #[default]
+    Inline,
+    /// A single `<span>` per block of text with `class` attributes starting with the given
+    /// prefix, such as `ansi-fg-1 ansi-bg-4 ansi-bold`. The classes must be defined by a
+    /// stylesheet, which can be generated using [`stylesheet`].
+    Class { prefix: String },
+}
+
+impl StyleMode {
+    /// Use [`StyleMode::Class`] with the default `ansi-` prefix.
+    #[must_use]
+    pub fn class() -> Self {
+        StyleMode::Class {
+            prefix: "ansi-".to_string(),
+        }
+    }
+}
+
+/// How hyperlinks using the `file` scheme are rendered.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub enum FileLinkPolicy {
+    /// Render the hyperlink as-is.
+    #[default]
+    Keep,
+    /// Render the text without a hyperlink.
+    Drop,
+    /// Replace the scheme and host of the hyperlink with the given base URL, keeping the path.
+    Rewrite { base_url: String },
+}
+
+/// URL schemes that may be rendered as hyperlinks. Hyperlinks using any other scheme, such as
+/// `javascript`, are dropped.
+static HYPERLINK_SCHEMES: [&str; 5] = ["http", "https", "ftp", "mailto", "file"];
+
+impl FileLinkPolicy {
+    /// Returns the URL a hyperlink should be rendered with, or `None` if the text should be
+    /// rendered without a hyperlink.
+    fn resolve(&self, url: &str) -> Option<String> {
+        let (scheme, rest) = url.split_once(':')?;
+        let scheme = scheme.to_lowercase();
+        if !HYPERLINK_SCHEMES.contains(&scheme.as_str()) {
+            return None;
+        }
+        if scheme != "file" {
+            return Some(url.to_string());
+        }
+        match self {
+            FileLinkPolicy::Keep => Some(url.to_string()),
+            FileLinkPolicy::Drop => None,
+            FileLinkPolicy::Rewrite { base_url } => {
+                // file://host/path, where the host may be empty
+                let path = match rest.strip_prefix("//") {
+                    Some(authority) => &authority[authority.find('/').unwrap_or(authority.len())..],
+                    None => rest,
+                };
+                Some(format!("{}{path}", base_url.trim_end_matches('/')))
             }
-            _ => (),
         }
+    }
+}
 
-        match self.background_color {
-            SgrColor::Console(n @ 0..=7) => {
-                let span = format!(
-                    "<span style=\"background-color: var(--color-{})\">",
-                    COLORS[n as usize]
-                );
-                opening_tags.push(span);
-                closing_tags.push("</span>".to_string());
+/// How colors are adjusted to be readable on the page the output is rendered in.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub enum ColorAdjustment {
+    /// Render colors as-is.
+    #[default]
+    None,
+    /// Adjust the lightness of foreground colors to meet a minimum [WCAG 2] contrast ratio
+    /// against the color behind them, keeping their hue and saturation. Text without a background
+    /// color is shown on the given background. Colors are resolved using the given palette and
+    /// adjusted colors are rendered as truecolor.
+    ///
+    /// [WCAG 2]: https://www.w3.org/TR/WCAG21/#dfn-contrast-ratio
+    Contrast {
+        background: palette::Rgb,
+        palette: palette::Palette,
+        minimum_ratio: f64,
+    },
+    /// Replace truecolor colors with the nearest of the 256 standard colors of the given palette,
+    /// so that they follow the theme of the page.
+    Quantize { palette: palette::Palette },
+}
+
+impl ColorAdjustment {
+    /// Returns the style with its colors adjusted, borrowing the original style if no colors
+    /// were changed.
+    fn apply<'a>(&self, style: &'a Style) -> Cow<'a, Style> {
+        match self {
+            ColorAdjustment::None => Cow::Borrowed(style),
+            ColorAdjustment::Quantize { palette } => {
+                let quantize = |color: &SgrColor| match color {
+                    SgrColor::True(r, g, b) => {
+                        SgrColor::ExpandedConsole(palette.nearest(palette::Rgb(*r, *g, *b)))
+                    }
+                    color => color.clone(),
+                };
+                let color = quantize(&style.color);
+                let background_color = quantize(&style.background_color);
+                let underline_color = quantize(&style.underline_color);
+                if color == style.color
+                    && background_color == style.background_color
+                    && underline_color == style.underline_color
+                {
+                    return Cow::Borrowed(style);
+                }
+                Cow::Owned(Style {
+                    color,
+                    background_color,
+                    underline_color,
+                    ..style.clone()
+                })
+            }
+            ColorAdjustment::Contrast {
+                background,
+                palette,
+                minimum_ratio,
+            } => {
+                let (color, background_color) = style.resolved_colors();
+                let Some(foreground) = color.rgb(palette) else {
+                    return Cow::Borrowed(style);
+                };
+                let behind = match background_color.rgb(palette) {
+                    Some(behind) => behind,
+                    // The default foreground color of the page is behind inverted text
+                    None if style.inverse => return Cow::Borrowed(style),
+                    None => *background,
+                };
+                let adjusted = foreground.with_contrast(&behind, *minimum_ratio);
+                if adjusted == foreground {
+                    return Cow::Borrowed(style);
+                }
+                let adjusted = SgrColor::True(adjusted.0, adjusted.1, adjusted.2);
+                let mut style = style.clone();
+                if style.inverse {
+                    style.background_color = adjusted;
+                } else {
+                    style.color = adjusted;
+                }
+                Cow::Owned(style)
             }
-            SgrColor::ExpandedConsole(n) => {
-                let span = format!("<span style=\"background-color: var(--terminal-color-{n})\">");
-                opening_tags.push(span);
-                closing_tags.push("</span>".to_string());
+        }
+    }
+}
+
+/// Options for rendering an ANSI terminal output to HTML.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct RenderOptions {
+    /// How formatting is attached to the generated HTML.
+    pub style_mode: StyleMode,
+    /// Replay the output into a virtual terminal, rendering the final contents of the terminal
+    /// so that carriage returns, backspaces, tabs, cursor movement, and erase sequences are
+    /// interpreted.
+    pub emulate_terminal: bool,
+    /// The width of the virtual terminal, after which text wraps to the next line. When `None`,
+    /// lines never wrap. Only used when [`RenderOptions::emulate_terminal`] is set.
+    pub terminal_width: Option<usize>,
+    /// The height of the virtual terminal, which limits erasing the screen to the last lines of
+    /// the output. When `None`, the terminal is 24 rows tall. Only used when
+    /// [`RenderOptions::emulate_terminal`] is set.
+    pub terminal_height: Option<usize>,
+    /// How [OSC 8] hyperlinks using the `file` scheme are rendered.
+    ///
+    /// [OSC 8]: https://gist.github.com/egmontkob/eb114294efbcd5adb1944c9f3cb5feda
+    pub file_links: FileLinkPolicy,
+    /// Produce minimal markup: adjacent blocks of text with the same formatting are merged, each
+    /// block is rendered as a single element combining every style or class, and whitespace is
+    /// left unformatted when the formatting wouldn't be visible on it.
+    pub compact: bool,
+    /// How colors are adjusted to be readable on the page the output is rendered in.
+    pub color_adjustment: ColorAdjustment,
+    /// The lines that are rendered, numbered from 1. When `None`, every line is rendered.
+    /// Formatting from lines before the range still applies to the rendered lines.
+    pub lines: Option<RangeInclusive<usize>>,
+    /// Wrap every line in a `<span class="ansi_line">` with its line number in a `data-line`
+    /// attribute, which can be shown using CSS.
+    pub line_numbers: bool,
+    /// Wrap every line in a `<span class="ansi_line">`, adding the `ansi_line_highlight` class to
+    /// lines within any of the ranges. Lines are numbered from 1, regardless of
+    /// [`RenderOptions::lines`].
+    pub highlight_lines: Vec<RangeInclusive<usize>>,
+    /// Collapse every run of lines within any of the ranges into a `<details class="ansi_fold">`
+    /// element, with a summary showing how many lines are hidden. Lines are numbered from 1,
+    /// regardless of [`RenderOptions::lines`].
+    pub fold_lines: Vec<RangeInclusive<usize>>,
+}
+
+/// Converts sequences into blocks of text, alongside the state each block of text should be
+/// formatted with. When [`RenderOptions::emulate_terminal`] is set, sequences are replayed into a
+/// [`terminal::Terminal`] and blocks are produced once the lines containing them are final.
+struct BlockParser {
+    state: Style,
+    terminal: Option<terminal::Terminal>,
+}
+
+impl BlockParser {
+    fn new(options: &RenderOptions) -> Self {
+        BlockParser {
+            state: Style::default(),
+            terminal: options
+                .emulate_terminal
+                .then(|| terminal::Terminal::new(options.terminal_width, options.terminal_height)),
+        }
+    }
+
+    /// Interpret a sequence, passing any blocks of text that are produced to the callback.
+    fn feed<E>(
+        &mut self,
+        sequence: &parser::Sequence,
+        f: &mut impl FnMut(&Style, &str) -> Result<(), E>,
+    ) -> Result<(), E> {
+        if let Some(terminal) = &mut self.terminal {
+            terminal.feed(sequence);
+            for (state, text) in terminal.take_final_lines() {
+                f(&state, text.as_str())?;
             }
-            SgrColor::True(r, g, b) => {
-                let span = format!("<span style=\"background-color: rgb({r}, {g}, {b})\">");
-                opening_tags.push(span);
-                closing_tags.push("</span>".to_string());
+        } else if let parser::Sequence::Text(text) = sequence {
+            f(&self.state, text)?;
+        } else if let Some(params) = sequence.sgr_params() {
+            self.state = self.state.clone_from_params(&params);
+        } else if let Some(hyperlink) = sequence.hyperlink() {
+            self.state.hyperlink = hyperlink.map(String::from);
+        }
+        // Trash all Escapes that aren't stylish
+        Ok(())
+    }
+
+    /// Pass any remaining blocks of text to the callback.
+    fn finish<E>(self, f: &mut impl FnMut(&Style, &str) -> Result<(), E>) -> Result<(), E> {
+        if let Some(terminal) = self.terminal {
+            for (state, text) in terminal.into_blocks() {
+                f(&state, text.as_str())?;
             }
-            _ => (),
         }
+        Ok(())
+    }
+}
 
-        (
-            opening_tags.join(""),
-            closing_tags.into_iter().rev().collect::<String>(),
-        )
+/// Format a number without trailing zeroes, rounded to two decimal places, for use in generated
+/// CSS and SVG.
+fn number(value: f64) -> String {
+    let value = format!("{value:.2}");
+    value
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+/// Parse an ANSI terminal output into blocks of text, alongside the state each block of text
+/// should be formatted with.
+fn parse_blocks(input: &str, options: &RenderOptions) -> Vec<(Style, String)> {
+    let mut blocks = vec![];
+    let mut push = |state: &Style, text: &str| {
+        blocks.push((state.clone(), text.to_string()));
+        Ok::<(), core::convert::Infallible>(())
+    };
+
+    let mut block_parser = BlockParser::new(options);
+    for sequence in parser::parse(input) {
+        let Ok(()) = block_parser.feed(&sequence, &mut push);
+    }
+    let Ok(()) = block_parser.finish(&mut push);
+
+    blocks
+}
+
+/// A block of text alongside the style it is formatted with.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StyledSpan {
+    pub style: Style,
+    pub text: String,
+}
+
+/// Parse an ANSI terminal output into spans of text, alongside the style each span is formatted
+/// with. See [`parse_styled_with`] to replay the output into a virtual terminal.
+#[must_use]
+pub fn parse_styled(input: &str) -> Vec<StyledSpan> {
+    parse_styled_with(input, &RenderOptions::default())
+}
+
+/// Parse an ANSI terminal output into spans of text, alongside the style each span is formatted
+/// with. Adjacent spans never have the same style. Only [`RenderOptions::emulate_terminal`] and
+/// [`RenderOptions::terminal_width`] affect parsing.
+#[must_use]
+pub fn parse_styled_with(input: &str, options: &RenderOptions) -> Vec<StyledSpan> {
+    let mut spans: Vec<StyledSpan> = vec![];
+    for (style, text) in parse_blocks(input, options) {
+        match spans.last_mut() {
+            Some(span) if span.style == style => span.text.push_str(&text),
+            _ if text.is_empty() => (),
+            _ => spans.push(StyledSpan { style, text }),
+        }
     }
+    spans
 }
 
 /// Render an ANSI terminal output to HTML, using [SGR] parameters to generate formatting.
 ///
 /// [SGR]: https://en.wikipedia.org/wiki/ANSI_escape_code#SGR_(Select_Graphic_Rendition)_parameters
-#[cfg_attr(feature = "tracing", tracing::instrument(skip(input)))]
 pub fn rewrite_ansi_to_html(input: &str) -> String {
+    rewrite_ansi_to_html_with(input, &RenderOptions::default())
+}
+
+/// Render an ANSI terminal output to HTML, using [SGR] parameters to generate formatting and the
+/// given [`RenderOptions`] to determine how formatting is represented. See [`AnsiHtmlWriter`] to
+/// render output as it is read.
+///
+/// [SGR]: https://en.wikipedia.org/wiki/ANSI_escape_code#SGR_(Select_Graphic_Rendition)_parameters
+#[cfg_attr(feature = "tracing", tracing::instrument(skip(input)))]
+pub fn rewrite_ansi_to_html_with(input: &str, options: &RenderOptions) -> String {
     #[cfg(feature = "tracing")]
-    debug!("parsing ANSI escape codes");
-
-    let parsed: Vec<Output> = input
-        .ansi_parse()
-        .filter(|value| {
-            // Trash all Escapes that aren't stylish
-            matches!(
-                value,
-                Output::Escape(AnsiSequence::SetGraphicsMode(_)) | Output::TextBlock(_)
-            )
-        })
-        .collect();
+    debug!("converting ANSI escape code and text chunks to HTML");
 
-    let mut state = GraphicsModeState::default();
-    let mut output = vec![];
+    writer::render_html(input, options)
+}
 
-    output.push("<pre class=\"ansi_output\"><code>".to_string());
+/// Generate a `:root` block defining the `--color-{c}` and `--color-bright-{c}` CSS variables used
+/// by [`StyleMode::Inline`] in terms of the `--terminal-color-{n}` variables defined by
+/// [`palette::Palette::stylesheet`], for pages that don't define their own colors.
+#[must_use]
+pub fn color_variables() -> String {
+    let mut output = vec![":root {".to_string()];
+    for (n, name) in COLORS.iter().enumerate() {
+        output.push(format!("\t--color-{name}: var(--terminal-color-{n});"));
+        output.push(format!(
+            "\t--color-bright-{name}: var(--terminal-color-{});",
+            n + 8
+        ));
+    }
+    output.push("}".to_string());
+    output.join("\n")
+}
 
-    #[cfg(feature = "tracing")]
-    debug!("converting ANSI escape code and text chunks to HTML");
+/// Generate the stylesheet required by HTML rendered from an ANSI terminal output using
+/// [`StyleMode::Class`]. Rules for every attribute and 256-color class are always included,
+/// referring to the `--terminal-color-{n}` CSS variables, alongside rules for every truecolor
+/// value used by the input. When [`StyleMode::Inline`] is used, no stylesheet is required and an
+/// empty string is returned.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip(input)))]
+pub fn stylesheet(input: &str, options: &RenderOptions) -> String {
+    let StyleMode::Class { prefix } = &options.style_mode else {
+        return String::new();
+    };
 
-    for block in parsed {
-        match block {
-            Output::Escape(AnsiSequence::SetGraphicsMode(mode)) => {
-                state = state.clone_from_scan(&mode[..]);
-            }
-            Output::TextBlock(text) => {
-                let (opening_tags, closing_tags) = state.build_tags();
-                let text = html_escape::encode_text(text);
-                output.push(format!("{opening_tags}{text}{closing_tags}"));
+    let mut rules = vec![
+        format!(".{prefix}bold {{ font-weight: bold; }}"),
+        format!(".{prefix}dim {{ opacity: 0.5; }}"),
+        format!(".{prefix}italic {{ font-style: italic; }}"),
+        format!(".{prefix}hidden {{ visibility: hidden; }}"),
+        format!(".{prefix}double-underline {{ text-decoration-style: double; }}"),
+        format!(".{prefix}underline-wavy {{ text-decoration-style: wavy; }}"),
+        format!(".{prefix}underline-dotted {{ text-decoration-style: dotted; }}"),
+        format!(".{prefix}underline-dashed {{ text-decoration-style: dashed; }}"),
+    ];
+
+    // text-decoration-line doesn't compose across classes, so every combination has a rule
+    let lines = [
+        ("underline", "underline"),
+        ("double-underline", "underline"),
+        ("strikethrough", "line-through"),
+        ("overline", "overline"),
+    ];
+    for combination in 1..(1 << lines.len()) {
+        // underline and double underline are mutually exclusive
+        if combination & 0b11 == 0b11 {
+            continue;
+        }
+        let selected: Vec<_> = lines
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| combination & (1 << i) != 0)
+            .map(|(_, line)| line)
+            .collect();
+        let selector: String = selected
+            .iter()
+            .map(|(class, _)| format!(".{prefix}{class}"))
+            .collect();
+        let values: Vec<_> = selected.iter().map(|(_, value)| *value).collect();
+        rules.push(format!(
+            "{selector} {{ text-decoration-line: {}; }}",
+            values.join(" ")
+        ));
+    }
+
+    rules.push(format!(
+        ".{prefix}fg-inverse {{ color: {DEFAULT_BACKGROUND}; }}"
+    ));
+    rules.push(format!(
+        ".{prefix}bg-inverse {{ background-color: {DEFAULT_FOREGROUND}; }}"
+    ));
+
+    for n in 0..=255 {
+        rules.push(format!(
+            ".{prefix}fg-{n} {{ color: var(--terminal-color-{n}); }}"
+        ));
+        rules.push(format!(
+            ".{prefix}bg-{n} {{ background-color: var(--terminal-color-{n}); }}"
+        ));
+        rules.push(format!(
+            ".{prefix}ul-{n} {{ text-decoration-color: var(--terminal-color-{n}); }}"
+        ));
+    }
+
+    let mut true_colors = vec![];
+    for (state, _) in parse_blocks(input, options) {
+        let state = options.color_adjustment.apply(&state);
+        let (color, background_color) = state.resolved_colors();
+        let colors = [
+            ("fg", color),
+            ("bg", background_color),
+            ("ul", &state.underline_color),
+        ];
+        for (kind, color) in colors {
+            if let SgrColor::True(..) = color {
+                if !true_colors.contains(&(kind, color.clone())) {
+                    true_colors.push((kind, color.clone()));
+                }
             }
-            Output::Escape(_) => {}
         }
     }
+    for (kind, color) in true_colors {
+        let (Some(class), Some(value)) = (color.class_name(prefix, kind), color.css_value()) else {
+            continue;
+        };
+        let property = match kind {
+            "fg" => "color",
+            "bg" => "background-color",
+            _ => "text-decoration-color",
+        };
+        rules.push(format!(".{class} {{ {property}: {value}; }}"));
+    }
 
-    output.push("</code></pre>".to_string());
-    output.join("")
+    rules.join("\n")
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use console::Style;
+    use ansi_parser::{AnsiParser, AnsiSequence, Output};
+    use console::Style as ConsoleStyle;
 
     #[test]
     fn parsing_basic_colors() {
         // names are slightly different but it's :ok_hand:
         let colors = [
-            Style::new().black(),
-            Style::new().red(),
-            Style::new().green(),
-            Style::new().yellow(),
-            Style::new().blue(),
-            Style::new().magenta(),
-            Style::new().cyan(),
-            Style::new().white(),
+            ConsoleStyle::new().black(),
+            ConsoleStyle::new().red(),
+            ConsoleStyle::new().green(),
+            ConsoleStyle::new().yellow(),
+            ConsoleStyle::new().blue(),
+            ConsoleStyle::new().magenta(),
+            ConsoleStyle::new().cyan(),
+            ConsoleStyle::new().white(),
         ];
-        let mut state = GraphicsModeState::default();
+        let mut state = Style::default();
         for (i, color) in colors.iter().enumerate() {
             let color_text = color.apply_to(" ").to_string();
             let color_code = color_text.ansi_parse().next().unwrap();
@@ -264,10 +1082,51 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parsing_bright_colors() {
+        let mut state = Style::default();
+        for n in 90..=97 {
+            state = state.clone_from_scan(&[n]);
+            assert_eq!(state.color, SgrColor::Bright(n - 90));
+        }
+        for n in 100..=107 {
+            state = state.clone_from_scan(&[n]);
+            assert_eq!(state.background_color, SgrColor::Bright(n - 100));
+        }
+        // Bright colors should still be overwritten by a later color in the same sequence
+        state = state.clone_from_scan(&[1, 90, 31, 101, 41]);
+        assert_eq!(state.color, SgrColor::Console(1));
+        assert_eq!(state.background_color, SgrColor::Console(1));
+    }
+
+    #[test]
+    fn rendering_bright_colors() {
+        for (n, name) in COLORS.iter().enumerate() {
+            let state = Style {
+                color: SgrColor::Bright(n as u8),
+                background_color: SgrColor::Bright(n as u8),
+                ..Default::default()
+            };
+            let (opening, closing) = state.build_tags();
+            assert_eq!(
+                opening,
+                format!(
+                    "<span style=\"color: var(--color-bright-{name})\">\
+                     <span style=\"background-color: var(--color-bright-{name})\">"
+                )
+            );
+            assert_eq!(closing, "</span></span>");
+        }
+
+        let css = color_variables();
+        assert!(css.contains("\t--color-gray: var(--terminal-color-7);"));
+        assert!(css.contains("\t--color-bright-red: var(--terminal-color-9);"));
+    }
+
     #[test]
     fn parsing_expanded_colors() {
-        let colors = (0..=255).map(|c| Style::new().color256(c));
-        let mut state = GraphicsModeState::default();
+        let colors = (0..=255).map(|c| ConsoleStyle::new().color256(c));
+        let mut state = Style::default();
         for (i, color) in colors.enumerate() {
             let color_text = color.apply_to(" ").to_string();
             let color_code = color_text.ansi_parse().next().unwrap();
@@ -288,7 +1147,7 @@ mod tests {
     #[test]
     fn parsing_styling() {
         // console-rs doesn't implement strikethrough :(
-        let styled_text = Style::new()
+        let styled_text = ConsoleStyle::new()
             .bold()
             .italic()
             .underlined()
@@ -296,13 +1155,13 @@ mod tests {
             .to_string();
         let mut style_codes: Vec<_> = styled_text.ansi_parse().collect();
         let reset = style_codes.pop().expect("no reset code"); // remove the reset code
-        let expected_state = GraphicsModeState {
+        let expected_state = Style {
             bold: true,
             italic: true,
             underline: true,
             ..Default::default()
         };
-        let mut state = GraphicsModeState::default();
+        let mut state = Style::default();
         for code in style_codes {
             state = match code {
                 Output::Escape(AnsiSequence::SetGraphicsMode(code)) => state.clone_from_scan(&code),
@@ -312,8 +1171,349 @@ mod tests {
         assert_eq!(state, expected_state);
         if let Output::Escape(AnsiSequence::SetGraphicsMode(code)) = reset {
             state = state.clone_from_scan(&code);
-            assert_eq!(state, GraphicsModeState::default());
+            assert_eq!(state, Style::default());
+        }
+    }
+
+    #[test]
+    fn parsing_attributes_on_and_off() {
+        type Getter = fn(&Style) -> bool;
+        let attributes: [(u8, u8, Getter); 8] = [
+            (1, 22, |s| s.bold),
+            (2, 22, |s| s.dim),
+            (3, 23, |s| s.italic),
+            (4, 24, |s| s.underline),
+            (21, 24, |s| s.double_underline),
+            (7, 27, |s| s.inverse),
+            (8, 28, |s| s.hidden),
+            (9, 29, |s| s.strikethrough),
+        ];
+        for (on, off, get) in attributes {
+            let state = Style::default().clone_from_scan(&[on]);
+            assert!(get(&state), "{on} didn't set attribute");
+            let state = state.clone_from_scan(&[off]);
+            assert!(!get(&state), "{off} didn't unset attribute set by {on}");
+            assert_eq!(state, Style::default());
+        }
+
+        let state = Style::default().clone_from_scan(&[53]);
+        assert!(state.overline);
+        assert_eq!(state.clone_from_scan(&[55]), Style::default());
+    }
+
+    #[test]
+    fn parsing_underline_variants() {
+        let state = Style::default().clone_from_scan(&[4, 21]);
+        assert!(!state.underline);
+        assert!(state.double_underline);
+        let state = state.clone_from_scan(&[4]);
+        assert!(state.underline);
+        assert!(!state.double_underline);
+    }
+
+    #[test]
+    fn rendering_inverse_colors() {
+        let state = Style::default().clone_from_scan(&[7]);
+        assert_eq!(
+            state.build_tags().0,
+            "<span style=\"color: var(--color-background)\">\
+             <span style=\"background-color: var(--color-text)\">"
+        );
+
+        let state = state.clone_from_scan(&[31, 44]);
+        assert_eq!(
+            state.build_tags().0,
+            "<span style=\"color: var(--color-blue)\">\
+             <span style=\"background-color: var(--color-red)\">"
+        );
+
+        let state = state.clone_from_scan(&[27, 49]);
+        assert_eq!(
+            state.build_tags().0,
+            "<span style=\"color: var(--color-red)\">"
+        );
+    }
+
+    #[test]
+    fn rendering_attributes() {
+        let state = Style::default().clone_from_scan(&[2, 8, 21, 53]);
+        let (opening, closing) = state.build_tags();
+        assert_eq!(
+            opening,
+            "<u style=\"text-decoration-style: double\">\
+             <span style=\"text-decoration: overline; opacity: 0.5; visibility: hidden\">"
+        );
+        assert_eq!(closing, "</span></u>");
+    }
+
+    #[test]
+    fn rendering_combined_styles() {
+        let state = Style::default().clone_from_scan(&[1, 3, 9, 21, 53, 7, 32]);
+        assert_eq!(
+            state.build_styles(),
+            [
+                "font-weight: bold",
+                "font-style: italic",
+                "text-decoration-line: underline line-through overline",
+                "text-decoration-style: double",
+                "color: var(--color-background)",
+                "background-color: var(--color-green)",
+            ]
+        );
+        assert!(Style::default().build_styles().is_empty());
+
+        let state = Style::default().clone_from_scan(&[1, 2, 31]);
+        assert!(state.is_foreground_only());
+        assert!(!state.clone_from_scan(&[41]).is_foreground_only());
+        assert!(!state.clone_from_scan(&[4]).is_foreground_only());
+    }
+
+    /// Parse the SGR parameters of a sequence, `ESC [ params m`.
+    fn parse_params(params: &str) -> Vec<parser::SgrParam> {
+        let sequence = format!("\x1b[{params}m");
+        let (sequence, _) = parser::split_sequence(&sequence).unwrap();
+        sequence.sgr_params().unwrap()
+    }
+
+    #[test]
+    fn parsing_subparameters() {
+        let state = Style::default().clone_from_params(&parse_params("38:2::255:128:0;48:5:4"));
+        assert_eq!(state.color, SgrColor::True(255, 128, 0));
+        assert_eq!(state.background_color, SgrColor::ExpandedConsole(4));
+
+        // Without the color space, as written by some terminals
+        let state = state.clone_from_params(&parse_params("38:2:1:2:3"));
+        assert_eq!(state.color, SgrColor::True(1, 2, 3));
+
+        let state = state.clone_from_params(&parse_params("4:3;58:5:1"));
+        assert!(state.underline);
+        assert_eq!(state.underline_style, UnderlineStyle::Curly);
+        assert_eq!(state.underline_color, SgrColor::ExpandedConsole(1));
+
+        let state = state.clone_from_params(&parse_params("4:2;58;2;0;0;255"));
+        assert!(!state.underline && state.double_underline);
+        assert_eq!(state.underline_color, SgrColor::True(0, 0, 255));
+
+        let state = state.clone_from_params(&parse_params("4:0;59"));
+        assert!(!state.underline && !state.double_underline);
+        assert_eq!(state.underline_color, SgrColor::Reset);
+    }
+
+    #[test]
+    fn ignoring_malformed_parameters() {
+        let red = Style::default().clone_from_scan(&[31]);
+
+        // A truncated extended color ignores the rest of the sequence rather than misreading it
+        for params in ["38;5", "38;2;255;0", "38;5;256;1", "48;3;1;1"] {
+            assert_eq!(red.clone_from_params(&parse_params(params)), red);
         }
+        let state = red.clone_from_params(&parse_params("1;38;2;255;0"));
+        assert_eq!(state, red.clone_from_scan(&[1]));
+
+        // Malformed subparameters only ignore their own parameter
+        let state = red.clone_from_params(&parse_params("38:5;1;4:9;58:2:1;3"));
+        assert_eq!(state, red.clone_from_scan(&[1, 3]));
+    }
+
+    #[test]
+    fn rendering_underline_styles() {
+        let state = Style::default().clone_from_params(&parse_params("4:3;58:5:1"));
+        assert_eq!(
+            state.build_styles(),
+            [
+                "text-decoration-line: underline",
+                "text-decoration-style: wavy",
+                "text-decoration-color: var(--terminal-color-1)",
+            ]
+        );
+        assert_eq!(
+            state.build_tags().0,
+            "<u style=\"text-decoration-style: wavy; \
+             text-decoration-color: var(--terminal-color-1)\">"
+        );
+        assert_eq!(
+            state.build_classes("ansi-"),
+            ["ansi-underline", "ansi-underline-wavy", "ansi-ul-1"]
+        );
+
+        // The underline color isn't visible without an underline
+        let state = state.clone_from_scan(&[24]);
+        assert!(state.build_styles().is_empty());
+    }
+
+    #[test]
+    fn rendering_classes() {
+        let state = Style::default().clone_from_scan(&[1, 31, 44]);
+        assert_eq!(
+            state.build_class_tags("ansi-").0,
+            "<span class=\"ansi-bold ansi-fg-1 ansi-bg-4\">"
+        );
+
+        let state = state.clone_from_scan(&[0, 3, 94, 48, 5, 200]);
+        assert_eq!(
+            state.build_class_tags("x-").0,
+            "<span class=\"x-italic x-fg-12 x-bg-200\">"
+        );
+
+        let state = state.clone_from_scan(&[0, 7, 38, 2, 255, 136, 0]);
+        assert_eq!(
+            state.build_class_tags("ansi-").0,
+            "<span class=\"ansi-fg-inverse ansi-bg-rgb-ff8800\">"
+        );
+
+        assert_eq!(
+            Style::default().build_class_tags("ansi-"),
+            (String::new(), String::new())
+        );
+    }
+
+    #[test]
+    fn rewriting_ansi_to_html_with_classes() {
+        let options = RenderOptions {
+            style_mode: StyleMode::class(),
+            ..Default::default()
+        };
+        let input = "\x1b[1;31mred\x1b[0m plain \x1b[38;2;1;2;3mtrue\x1b[0m";
+        assert_eq!(
+            rewrite_ansi_to_html_with(input, &options),
+            "<pre class=\"ansi_output\"><code>\
+             <span class=\"ansi-bold ansi-fg-1\">red</span> plain \
+             <span class=\"ansi-fg-rgb-010203\">true</span>\
+             </code></pre>"
+        );
+
+        let css = stylesheet(input, &options);
+        assert!(css.contains(".ansi-bold { font-weight: bold; }"));
+        assert!(css.contains(".ansi-fg-1 { color: var(--terminal-color-1); }"));
+        assert!(css.contains(".ansi-bg-255 { background-color: var(--terminal-color-255); }"));
+        assert!(css.contains(".ansi-fg-rgb-010203 { color: rgb(1, 2, 3); }"));
+        assert!(css.contains(
+            ".ansi-underline.ansi-strikethrough { text-decoration-line: underline line-through; }"
+        ));
+        assert!(!css.contains(".ansi-underline.ansi-double-underline"));
+
+        assert_eq!(stylesheet(input, &RenderOptions::default()), "");
+    }
+
+    #[test]
+    fn rewriting_ansi_to_html_with_terminal() {
+        let options = RenderOptions {
+            emulate_terminal: true,
+            ..Default::default()
+        };
+        let input = "\x1b[32mBuilding\x1b[0m [=> ] 1/2\r\x1b[32mBuilding\x1b[0m [==>] 2/2\n";
+        assert_eq!(
+            rewrite_ansi_to_html_with(input, &options),
+            "<pre class=\"ansi_output\"><code>\
+             <span style=\"color: var(--color-green)\">Building</span> [==&gt;] 2/2\n\
+             </code></pre>"
+        );
+    }
+
+    #[test]
+    fn rewriting_ansi_to_html_with_terminal_movement() {
+        let options = RenderOptions {
+            emulate_terminal: true,
+            ..Default::default()
+        };
+        let input = "\x1b[65535C".repeat(1600) + "x";
+        let html = rewrite_ansi_to_html_with(&input, &options);
+        assert!(html.len() < 2048);
+        assert!(html.ends_with(" x</code></pre>"));
+    }
+
+    #[test]
+    fn adjusting_colors() {
+        let palette = palette::Palette::default();
+        let contrast = ColorAdjustment::Contrast {
+            background: palette::Rgb(0xcb, 0xcb, 0xcb),
+            palette: palette.clone(),
+            minimum_ratio: 4.5,
+        };
+
+        // Yellow on the light background is darkened, but not on a black background
+        let yellow = Style::default().clone_from_scan(&[93]);
+        assert!(matches!(
+            contrast.apply(&yellow).color,
+            SgrColor::True(r, g, 0) if r == g && r < 0x80
+        ));
+        let on_black = yellow.clone_from_scan(&[40]);
+        assert!(matches!(contrast.apply(&on_black), Cow::Borrowed(_)));
+        assert!(matches!(
+            contrast.apply(&Style::default()),
+            Cow::Borrowed(_)
+        ));
+
+        // Inverted text adjusts the background color, which is shown in front
+        let inverse = Style::default().clone_from_scan(&[7, 33, 43]);
+        let adjusted = contrast.apply(&inverse);
+        assert_eq!(adjusted.color, SgrColor::Console(3));
+        assert!(matches!(adjusted.background_color, SgrColor::True(..)));
+
+        let quantize = ColorAdjustment::Quantize { palette };
+        let orange = Style::default().clone_from_scan(&[38, 2, 255, 128, 0, 48, 2, 0, 0, 1]);
+        let quantized = quantize.apply(&orange);
+        assert_eq!(quantized.color, SgrColor::ExpandedConsole(208));
+        assert_eq!(quantized.background_color, SgrColor::ExpandedConsole(0));
+        assert!(matches!(quantize.apply(&yellow), Cow::Borrowed(_)));
+
+        let options = RenderOptions {
+            color_adjustment: quantize,
+            ..Default::default()
+        };
+        assert_eq!(
+            rewrite_ansi_to_html_with("\x1b[38;2;205;0;0mred", &options),
+            "<pre class=\"ansi_output\"><code>\
+             <span style=\"color: var(--terminal-color-1)\">red</span>\
+             </code></pre>"
+        );
+    }
+
+    #[test]
+    fn formatting_numbers() {
+        assert_eq!(number(8.399_999), "8.4");
+        assert_eq!(number(28.0), "28");
+        assert_eq!(number(0.125), "0.12");
+    }
+
+    #[test]
+    fn rewriting_hyperlinks() {
+        let input =
+            "\x1b]8;;https://example.com/?a=1&b=\"2\"\x1b\\\x1b[1mlink\x1b[0m!\x1b]8;;\x1b\\ no";
+        assert_eq!(
+            rewrite_ansi_to_html(input),
+            "<pre class=\"ansi_output\"><code>\
+             <a href=\"https://example.com/?a=1&amp;b=&quot;2&quot;\"><strong>link</strong></a>\
+             <a href=\"https://example.com/?a=1&amp;b=&quot;2&quot;\">!</a> no\
+             </code></pre>"
+        );
+
+        let input = "\x1b]8;id=1;javascript:alert(1)\x07x\x1b]8;;\x07";
+        assert_eq!(
+            rewrite_ansi_to_html(input),
+            "<pre class=\"ansi_output\"><code>x</code></pre>"
+        );
+    }
+
+    #[test]
+    fn rewriting_file_hyperlinks() {
+        let input = "\x1b]8;;file://host/src/main.rs\x07main.rs\x1b]8;;\x07";
+        let mut options = RenderOptions::default();
+        assert!(rewrite_ansi_to_html_with(input, &options)
+            .contains("<a href=\"file://host/src/main.rs\">main.rs</a>"));
+
+        options.file_links = FileLinkPolicy::Drop;
+        assert!(rewrite_ansi_to_html_with(input, &options).contains("<code>main.rs</code>"));
+
+        options.file_links = FileLinkPolicy::Rewrite {
+            base_url: "https://example.com/tree/".to_string(),
+        };
+        assert!(rewrite_ansi_to_html_with(input, &options)
+            .contains("<a href=\"https://example.com/tree/src/main.rs\">main.rs</a>"));
+        assert_eq!(
+            options.file_links.resolve("file:///etc/hosts"),
+            Some("https://example.com/tree/etc/hosts".to_string())
+        );
     }
 
     #[test]
@@ -0,0 +1,464 @@
+//! Convert ANSI terminal output to HTML from the command line, such as
+//! `cargo test --color=always | opaque-ansi --standalone > report.html`.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Parser, ValueEnum};
+use opaque_ansi::palette::{Palette, Rgb, Theme};
+use opaque_ansi::{
+    color_variables, stylesheet, AnsiHtmlWriter, ColorAdjustment, FileLinkPolicy, RenderOptions,
+    StyleMode,
+};
+
+/// Rules for the elements around rendered output in standalone documents.
+static DOCUMENT_RULES: &str = "\
+body {
+\tmargin: 0;
+\tpadding: 1rem;
+\tcolor: var(--color-text);
+\tbackground-color: var(--color-background);
+}
+pre.ansi_output {
+\tmargin: 0 0 1rem;
+\tfont-family: ui-monospace, SFMono-Regular, Menlo, Consolas, monospace;
+}
+.ansi_line {
+\tdisplay: block;
+}
+.ansi_line[data-line]::before {
+\tcontent: attr(data-line);
+\tdisplay: inline-block;
+\tmin-width: 4ch;
+\tmargin-right: 2ch;
+\ttext-align: right;
+\topacity: 0.5;
+\tuser-select: none;
+}
+.ansi_line_highlight {
+\tbackground-color: rgba(255, 255, 255, 0.1);
+}
+.ansi_fold > summary {
+\tcursor: pointer;
+\topacity: 0.5;
+\tuser-select: none;
+}";
+
+/// Convert ANSI terminal output to HTML
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Args {
+    /// Files containing terminal output, each rendered as its own block. Standard input is read
+    /// when no files are given, or for `-`
+    files: Vec<PathBuf>,
+
+    /// Write to a file rather than standard output
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+
+    /// Write a complete HTML document with the stylesheet inlined, rather than a fragment
+    #[arg(long)]
+    standalone: bool,
+
+    /// The title of the standalone document
+    #[arg(long, default_value = "Terminal output")]
+    title: String,
+
+    /// Write the stylesheet required by fragments to a file, to be included by the page they are
+    /// embedded in
+    #[arg(long, value_name = "FILE")]
+    css: Option<PathBuf>,
+
+    /// The built-in theme used for the 256 standard terminal colors
+    #[arg(long, default_value = "xterm")]
+    theme: Theme,
+
+    /// A file of `#rrggbb` colors, one per line, used instead of the theme
+    #[arg(long, value_name = "FILE")]
+    palette: Option<PathBuf>,
+
+    /// The color of text without a foreground color
+    #[arg(long, value_name = "COLOR", default_value = "#bcbcbc")]
+    foreground: Rgb,
+
+    /// The color of the page behind the output
+    #[arg(long, value_name = "COLOR", default_value = "#1c1c1c")]
+    background: Rgb,
+
+    /// Attach formatting using classes rather than inline styles. Classes start with `ansi-`, or
+    /// with the prefix given as `--classes=PREFIX`
+    #[arg(
+        long,
+        value_name = "PREFIX",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "ansi-"
+    )]
+    classes: Option<String>,
+
+    /// Replay the output into a virtual terminal, so that carriage returns, cursor movement, and
+    /// erase sequences are interpreted
+    #[arg(long)]
+    terminal: bool,
+
+    /// The width of the virtual terminal, after which text wraps to the next line
+    #[arg(long, requires = "terminal")]
+    columns: Option<usize>,
+
+    /// The height of the virtual terminal, which erasing the screen is limited to
+    #[arg(long, requires = "terminal")]
+    rows: Option<usize>,
+
+    /// Merge adjacent blocks of text with the same formatting, producing minimal markup
+    #[arg(long)]
+    compact: bool,
+
+    /// How hyperlinks to files are rendered
+    #[arg(long, value_enum, default_value_t = FileLinks::Keep)]
+    file_links: FileLinks,
+
+    /// Rewrite hyperlinks to files to start with the given base URL, keeping their path
+    #[arg(long, value_name = "URL", conflicts_with = "file_links")]
+    file_link_base: Option<String>,
+
+    /// Only render the given line or range of lines, such as `120-140` or `120-`
+    #[arg(long, value_name = "RANGE", value_parser = parse_line_range)]
+    lines: Option<RangeInclusive<usize>>,
+
+    /// Show line numbers
+    #[arg(long)]
+    line_numbers: bool,
+
+    /// Highlight the given lines and ranges of lines, such as `3,5-7`
+    #[arg(long, value_name = "RANGES", value_parser = parse_line_range, value_delimiter = ',')]
+    highlight: Vec<RangeInclusive<usize>>,
+
+    /// Collapse the given lines and ranges of lines into expandable sections
+    #[arg(long, value_name = "RANGES", value_parser = parse_line_range, value_delimiter = ',')]
+    fold: Vec<RangeInclusive<usize>>,
+
+    /// Lighten or darken text to meet a minimum contrast ratio against the background, such as
+    /// `4.5`
+    #[arg(long, value_name = "RATIO", conflicts_with = "quantize")]
+    min_contrast: Option<f64>,
+
+    /// Replace truecolor colors with the nearest of the 256 standard colors
+    #[arg(long)]
+    quantize: bool,
+}
+
+/// How hyperlinks to files are rendered, other than rewriting them.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum FileLinks {
+    /// Render the hyperlink as-is
+    Keep,
+    /// Render the text without a hyperlink
+    Drop,
+}
+
+/// Parse a line number or a range of line numbers, such as `5-7`. A range without an end, such as
+/// `120-`, continues until the last line.
+fn parse_line_range(input: &str) -> Result<RangeInclusive<usize>, String> {
+    let parse = |line: &str| {
+        line.trim()
+            .parse()
+            .map_err(|_| format!("invalid line number: {line:?}"))
+    };
+    Ok(match input.split_once('-') {
+        Some((start, "")) => parse(start)?..=usize::MAX,
+        Some((start, end)) => parse(start)?..=parse(end)?,
+        None => {
+            let line = parse(input)?;
+            line..=line
+        }
+    })
+}
+
+impl Args {
+    /// Returns the palette used to resolve the standard terminal colors.
+    fn palette(&self) -> Result<Palette, Box<dyn Error>> {
+        Ok(match &self.palette {
+            Some(path) => Palette::from_file(path)
+                .map_err(|error| format!("Unable to load {}: {error}", path.display()))?,
+            None => self.theme.palette(),
+        })
+    }
+
+    fn render_options(&self, palette: &Palette) -> RenderOptions {
+        let file_links = match (&self.file_link_base, self.file_links) {
+            (Some(base_url), _) => FileLinkPolicy::Rewrite {
+                base_url: base_url.clone(),
+            },
+            (None, FileLinks::Keep) => FileLinkPolicy::Keep,
+            (None, FileLinks::Drop) => FileLinkPolicy::Drop,
+        };
+        let color_adjustment = match self.min_contrast {
+            Some(minimum_ratio) => ColorAdjustment::Contrast {
+                background: self.background,
+                palette: palette.clone(),
+                minimum_ratio,
+            },
+            None if self.quantize => ColorAdjustment::Quantize {
+                palette: palette.clone(),
+            },
+            None => ColorAdjustment::None,
+        };
+
+        RenderOptions {
+            style_mode: match &self.classes {
+                Some(prefix) => StyleMode::Class {
+                    prefix: prefix.clone(),
+                },
+                None => StyleMode::Inline,
+            },
+            emulate_terminal: self.terminal,
+            terminal_width: self.columns,
+            terminal_height: self.rows,
+            file_links,
+            compact: self.compact,
+            color_adjustment,
+            lines: self.lines.clone(),
+            line_numbers: self.line_numbers,
+            highlight_lines: self.highlight.clone(),
+            fold_lines: self.fold.clone(),
+        }
+    }
+}
+
+/// Returns the inputs to render, where `-` is standard input, which is read when no files are
+/// given.
+fn input_paths(files: &[PathBuf]) -> Vec<PathBuf> {
+    if files.is_empty() {
+        vec![PathBuf::from("-")]
+    } else {
+        files.to_vec()
+    }
+}
+
+/// Open an input for reading, where `-` is standard input.
+fn open_input(path: &Path) -> Result<Box<dyn Read>, Box<dyn Error>> {
+    if path == Path::new("-") {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+    let file =
+        File::open(path).map_err(|error| format!("Unable to read {}: {error}", path.display()))?;
+    Ok(Box::new(file))
+}
+
+/// Read every input, substituting invalid UTF-8.
+fn read_inputs(paths: &[PathBuf]) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut inputs = vec![];
+    for path in paths {
+        let mut input = vec![];
+        open_input(path)?
+            .read_to_end(&mut input)
+            .map_err(|error| format!("Unable to read {}: {error}", path.display()))?;
+        inputs.push(String::from_utf8_lossy(&input).into_owned());
+    }
+    Ok(inputs)
+}
+
+/// Returns the stylesheet required by the rendered inputs, defining every color and class they
+/// refer to.
+fn build_stylesheet(
+    inputs: &[String],
+    palette: &Palette,
+    foreground: Rgb,
+    background: Rgb,
+    options: &RenderOptions,
+) -> String {
+    let mut rules = vec![
+        palette.stylesheet(),
+        color_variables(),
+        format!(":root {{\n\t--color-text: {foreground};\n\t--color-background: {background};\n}}"),
+    ];
+    // Only the truecolor values used are collected, which doesn't depend on where inputs end
+    let classes = stylesheet(&inputs.join("\n"), options);
+    if !classes.is_empty() {
+        rules.push(classes);
+    }
+    rules.join("\n")
+}
+
+/// Returns the start of a complete HTML document with the given stylesheet, which rendered output
+/// is written after, followed by [`DOCUMENT_END`].
+fn document_start(title: &str, stylesheet: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n\
+         <html lang=\"en\">\n\
+         <head>\n\
+         <meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{}</title>\n\
+         <style>\n{stylesheet}\n{DOCUMENT_RULES}\n</style>\n\
+         </head>\n\
+         <body>\n",
+        html_escape::encode_text(title),
+    )
+}
+
+/// The end of a document started by [`document_start`].
+static DOCUMENT_END: &str = "</body>\n</html>\n";
+
+fn run(args: &Args) -> Result<(), Box<dyn Error>> {
+    let palette = args.palette()?;
+    let options = args.render_options(&palette);
+    let paths = input_paths(&args.files);
+
+    // In class mode, the stylesheet has a rule for every truecolor value used, so inputs have to
+    // be read before any output is written. Otherwise, they're rendered as they're read.
+    let needs_stylesheet = args.standalone || args.css.is_some();
+    let inputs = if needs_stylesheet && args.classes.is_some() {
+        Some(read_inputs(&paths)?)
+    } else {
+        None
+    };
+
+    let css = needs_stylesheet.then(|| {
+        build_stylesheet(
+            inputs.as_deref().unwrap_or_default(),
+            &palette,
+            args.foreground,
+            args.background,
+            &options,
+        )
+    });
+    if let (Some(path), Some(css)) = (&args.css, &css) {
+        std::fs::write(path, format!("{css}\n"))
+            .map_err(|error| format!("Unable to write {}: {error}", path.display()))?;
+    }
+
+    let mut output: Box<dyn Write> = match &args.output {
+        Some(path) => {
+            Box::new(BufWriter::new(File::create(path).map_err(|error| {
+                format!("Unable to write {}: {error}", path.display())
+            })?))
+        }
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    if let (Some(css), true) = (&css, args.standalone) {
+        output.write_all(document_start(&args.title, css).as_bytes())?;
+    }
+    for (i, path) in paths.iter().enumerate() {
+        let mut writer = AnsiHtmlWriter::new(&mut output, options.clone());
+        match &inputs {
+            Some(inputs) => writer.write_all(inputs[i].as_bytes())?,
+            None => {
+                io::copy(&mut open_input(path)?, &mut writer)?;
+            }
+        }
+        writer.finish()?.write_all(b"\n")?;
+    }
+    if args.standalone {
+        output.write_all(DOCUMENT_END.as_bytes())?;
+    }
+    output.flush()?;
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("opaque-ansi: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing_arguments() {
+        let args = Args::parse_from([
+            "opaque-ansi",
+            "--classes",
+            "--highlight",
+            "3,5-7",
+            "--highlight",
+            "9",
+            "--lines",
+            "120-",
+            "--file-link-base",
+            "https://example.com/src",
+        ]);
+        let options = args.render_options(&Palette::default());
+        assert_eq!(options.style_mode, StyleMode::class());
+        assert_eq!(options.highlight_lines, vec![3..=3, 5..=7, 9..=9]);
+        assert_eq!(options.lines, Some(120..=usize::MAX));
+        assert_eq!(
+            options.file_links,
+            FileLinkPolicy::Rewrite {
+                base_url: "https://example.com/src".to_string()
+            }
+        );
+
+        // The prefix has to be given with `=`, so that a file isn't taken as the prefix
+        let args = Args::parse_from(["opaque-ansi", "--classes", "build.log"]);
+        assert_eq!(args.classes.as_deref(), Some("ansi-"));
+        assert_eq!(args.files, vec![PathBuf::from("build.log")]);
+        let args = Args::parse_from(["opaque-ansi", "--classes=ci-", "build.log"]);
+        assert_eq!(args.classes.as_deref(), Some("ci-"));
+        assert_eq!(args.files, vec![PathBuf::from("build.log")]);
+
+        assert!(Args::try_parse_from(["opaque-ansi", "--fold", "x"]).is_err());
+        assert!(Args::try_parse_from(["opaque-ansi", "--columns", "80"]).is_err());
+    }
+
+    #[test]
+    fn writing_documents() {
+        let inputs = vec!["\x1b[31mred\x1b[0m".to_string()];
+        let options = RenderOptions::default();
+        let css = build_stylesheet(
+            &inputs,
+            &Theme::Xterm.palette(),
+            Rgb(0xbc, 0xbc, 0xbc),
+            Rgb(0x1c, 0x1c, 0x1c),
+            &options,
+        );
+        assert!(css.contains("\t--terminal-color-1: #cd0000;"));
+        assert!(css.contains("\t--color-red: var(--terminal-color-1);"));
+        assert!(css.contains("\t--color-background: #1c1c1c;"));
+
+        let html = document_start("<cargo test>", &css);
+        assert!(html.starts_with("<!DOCTYPE html>\n"));
+        assert!(html.contains("<title>&lt;cargo test&gt;</title>"));
+        assert!(html.ends_with("<body>\n"));
+    }
+
+    #[test]
+    fn rendering_files() {
+        let input = concat!(env!("CARGO_MANIFEST_DIR"), "/src/test_data/input");
+        let expected = opaque_ansi::rewrite_ansi_to_html(&std::fs::read_to_string(input).unwrap());
+        let output = std::env::temp_dir().join(format!("opaque-ansi-{}", std::process::id()));
+
+        for mode in ["--compact", "--classes"] {
+            let args = Args::parse_from([
+                "opaque-ansi",
+                "--standalone",
+                mode,
+                "--output",
+                output.to_str().unwrap(),
+                input,
+                input,
+            ]);
+            run(&args).unwrap();
+            let html = std::fs::read_to_string(&output).unwrap();
+            assert!(html.starts_with("<!DOCTYPE html>\n"));
+            assert!(html.ends_with("</pre>\n</body>\n</html>\n"));
+            assert_eq!(html.matches("<pre class=\"ansi_output\">").count(), 2);
+        }
+
+        let args = Args::parse_from(["opaque-ansi", "--output", output.to_str().unwrap(), input]);
+        run(&args).unwrap();
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), expected + "\n");
+        std::fs::remove_file(output).unwrap();
+    }
+}
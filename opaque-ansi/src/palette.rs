@@ -0,0 +1,556 @@
+//! Terminal color palettes, used to define the `--terminal-color-{n}` CSS variables referred to by
+//! rendered ANSI terminal output.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+#[cfg(feature = "std")]
+use std::path::Path;
+
+/// Returns the least non-negative remainder of dividing by a positive modulus, like
+/// `f64::rem_euclid`, which requires `std`.
+fn rem_euclid(value: f64, modulus: f64) -> f64 {
+    let remainder = value % modulus;
+    if remainder < 0.0 {
+        remainder + modulus
+    } else {
+        remainder
+    }
+}
+
+/// An RGB color value.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl core::fmt::Display for Rgb {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let Rgb(r, g, b) = self;
+        write!(f, "#{r:02x}{g:02x}{b:02x}")
+    }
+}
+
+impl Rgb {
+    /// Returns the relative luminance of the color, from 0.0 for black to 1.0 for white, as
+    /// defined by [WCAG 2].
+    ///
+    /// [WCAG 2]: https://www.w3.org/TR/WCAG21/#dfn-relative-luminance
+    #[must_use]
+    pub fn relative_luminance(&self) -> f64 {
+        let channel = |value: u8| {
+            let value = f64::from(value) / 255.0;
+            if value <= 0.040_45 {
+                value / 12.92
+            } else {
+                libm::pow((value + 0.055) / 1.055, 2.4)
+            }
+        };
+        0.2126 * channel(self.0) + 0.7152 * channel(self.1) + 0.0722 * channel(self.2)
+    }
+
+    /// Returns the contrast ratio between two colors, from 1.0 for identical colors to 21.0 for
+    /// black and white, as defined by [WCAG 2].
+    ///
+    /// [WCAG 2]: https://www.w3.org/TR/WCAG21/#dfn-contrast-ratio
+    #[must_use]
+    pub fn contrast_ratio(&self, other: &Rgb) -> f64 {
+        let (a, b) = (self.relative_luminance(), other.relative_luminance());
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
+    /// Returns the hue in degrees, saturation, and lightness of the color.
+    fn to_hsl(self) -> (f64, f64, f64) {
+        let [r, g, b] = [self.0, self.1, self.2].map(|value| f64::from(value) / 255.0);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let lightness = (max + min) / 2.0;
+        let delta = max - min;
+        if delta == 0.0 {
+            return (0.0, 0.0, lightness);
+        }
+        let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+        let hue = if max == r {
+            60.0 * rem_euclid((g - b) / delta, 6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        (hue, saturation, lightness)
+    }
+
+    /// Create a color from a hue in degrees, saturation, and lightness.
+    fn from_hsl(hue: f64, saturation: f64, lightness: f64) -> Rgb {
+        let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+        let x = chroma * (1.0 - (rem_euclid(hue / 60.0, 2.0) - 1.0).abs());
+        let (r, g, b) = match hue {
+            h if h < 60.0 => (chroma, x, 0.0),
+            h if h < 120.0 => (x, chroma, 0.0),
+            h if h < 180.0 => (0.0, chroma, x),
+            h if h < 240.0 => (0.0, x, chroma),
+            h if h < 300.0 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let offset = lightness - chroma / 2.0;
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let channel = |value: f64| libm::round((value + offset) * 255.0).clamp(0.0, 255.0) as u8;
+        Rgb(channel(r), channel(g), channel(b))
+    }
+
+    /// Returns the color with the smallest change to its lightness that meets the minimum
+    /// contrast ratio against the background, keeping its hue and saturation. The color is made
+    /// darker or lighter, whichever allows more contrast against the background; if the minimum
+    /// can't be met, the darkest or lightest color is returned.
+    #[must_use]
+    pub fn with_contrast(&self, background: &Rgb, minimum_ratio: f64) -> Rgb {
+        if self.contrast_ratio(background) >= minimum_ratio {
+            return *self;
+        }
+        let (hue, saturation, lightness) = self.to_hsl();
+        let darken = Rgb(0, 0, 0).contrast_ratio(background)
+            >= Rgb(255, 255, 255).contrast_ratio(background);
+        let (mut low, mut high) = if darken {
+            (0.0, lightness)
+        } else {
+            (lightness, 1.0)
+        };
+        // Find the lightness closest to the original that still meets the minimum
+        for _ in 0..16 {
+            let middle = (low + high) / 2.0;
+            let meets_minimum =
+                Rgb::from_hsl(hue, saturation, middle).contrast_ratio(background) >= minimum_ratio;
+            if meets_minimum == darken {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        Rgb::from_hsl(hue, saturation, if darken { low } else { high })
+    }
+}
+
+impl core::str::FromStr for Rgb {
+    type Err = PaletteError;
+
+    /// Parse a color in the form of `#rrggbb`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || PaletteError::InvalidColor(s.to_string());
+        let hex = s.strip_prefix('#').ok_or_else(invalid)?;
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+        Ok(Rgb(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+/// An error encountered while loading a [`Palette`] or [`Theme`].
+#[derive(Debug)]
+pub enum PaletteError {
+    /// The palette file could not be read.
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
+    /// A color was not in the form of `#rrggbb`.
+    InvalidColor(String),
+    /// More than 256 colors were given.
+    TooManyColors,
+    /// The name did not match any built-in theme.
+    UnknownTheme(String),
+}
+
+impl core::fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            PaletteError::Io(e) => write!(f, "unable to read palette: {e}"),
+            PaletteError::InvalidColor(color) => {
+                write!(f, "invalid color, expected #rrggbb: {color}")
+            }
+            PaletteError::TooManyColors => write!(f, "palette has more than 256 colors"),
+            PaletteError::UnknownTheme(name) => write!(f, "unknown terminal theme: {name}"),
+        }
+    }
+}
+
+impl core::error::Error for PaletteError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "std")]
+            PaletteError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for PaletteError {
+    fn from(error: std::io::Error) -> Self {
+        PaletteError::Io(error)
+    }
+}
+
+/// The six levels of each channel in the 6x6x6 color cube, used for colors 16..=231.
+static CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The 16 standard colors used by xterm.
+static XTERM: [Rgb; 16] = [
+    Rgb(0x00, 0x00, 0x00),
+    Rgb(0xcd, 0x00, 0x00),
+    Rgb(0x00, 0xcd, 0x00),
+    Rgb(0xcd, 0xcd, 0x00),
+    Rgb(0x00, 0x00, 0xee),
+    Rgb(0xcd, 0x00, 0xcd),
+    Rgb(0x00, 0xcd, 0xcd),
+    Rgb(0xe5, 0xe5, 0xe5),
+    Rgb(0x7f, 0x7f, 0x7f),
+    Rgb(0xff, 0x00, 0x00),
+    Rgb(0x00, 0xff, 0x00),
+    Rgb(0xff, 0xff, 0x00),
+    Rgb(0x5c, 0x5c, 0xff),
+    Rgb(0xff, 0x00, 0xff),
+    Rgb(0x00, 0xff, 0xff),
+    Rgb(0xff, 0xff, 0xff),
+];
+
+/// The 16 standard colors used by IBM VGA text mode.
+static VGA: [Rgb; 16] = [
+    Rgb(0x00, 0x00, 0x00),
+    Rgb(0xaa, 0x00, 0x00),
+    Rgb(0x00, 0xaa, 0x00),
+    Rgb(0xaa, 0x55, 0x00),
+    Rgb(0x00, 0x00, 0xaa),
+    Rgb(0xaa, 0x00, 0xaa),
+    Rgb(0x00, 0xaa, 0xaa),
+    Rgb(0xaa, 0xaa, 0xaa),
+    Rgb(0x55, 0x55, 0x55),
+    Rgb(0xff, 0x55, 0x55),
+    Rgb(0x55, 0xff, 0x55),
+    Rgb(0xff, 0xff, 0x55),
+    Rgb(0x55, 0x55, 0xff),
+    Rgb(0xff, 0x55, 0xff),
+    Rgb(0x55, 0xff, 0xff),
+    Rgb(0xff, 0xff, 0xff),
+];
+
+/// The 16 standard colors used by Solarized, as mapped by its terminal color schemes.
+static SOLARIZED: [Rgb; 16] = [
+    Rgb(0x07, 0x36, 0x42),
+    Rgb(0xdc, 0x32, 0x2f),
+    Rgb(0x85, 0x99, 0x00),
+    Rgb(0xb5, 0x89, 0x00),
+    Rgb(0x26, 0x8b, 0xd2),
+    Rgb(0xd3, 0x36, 0x82),
+    Rgb(0x2a, 0xa1, 0x98),
+    Rgb(0xee, 0xe8, 0xd5),
+    Rgb(0x00, 0x2b, 0x36),
+    Rgb(0xcb, 0x4b, 0x16),
+    Rgb(0x58, 0x6e, 0x75),
+    Rgb(0x65, 0x7b, 0x83),
+    Rgb(0x83, 0x94, 0x96),
+    Rgb(0x6c, 0x71, 0xc4),
+    Rgb(0x93, 0xa1, 0xa1),
+    Rgb(0xfd, 0xf6, 0xe3),
+];
+
+/// The 16 standard colors used by the Tango desktop project.
+static TANGO: [Rgb; 16] = [
+    Rgb(0x2e, 0x34, 0x36),
+    Rgb(0xcc, 0x00, 0x00),
+    Rgb(0x4e, 0x9a, 0x06),
+    Rgb(0xc4, 0xa0, 0x00),
+    Rgb(0x34, 0x65, 0xa4),
+    Rgb(0x75, 0x50, 0x7b),
+    Rgb(0x06, 0x98, 0x9a),
+    Rgb(0xd3, 0xd7, 0xcf),
+    Rgb(0x55, 0x57, 0x53),
+    Rgb(0xef, 0x29, 0x29),
+    Rgb(0x8a, 0xe2, 0x34),
+    Rgb(0xfc, 0xe9, 0x4f),
+    Rgb(0x72, 0x9f, 0xcf),
+    Rgb(0xad, 0x7f, 0xa8),
+    Rgb(0x34, 0xe2, 0xe2),
+    Rgb(0xee, 0xee, 0xec),
+];
+
+/// A built-in terminal color theme.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Theme {
+    #[default]
+    Xterm,
+    Vga,
+    Solarized,
+    Tango,
+}
+
+impl Theme {
+    /// Returns the complete 256-color palette for the theme.
+    #[must_use]
+    pub fn palette(&self) -> Palette {
+        let base = match self {
+            Theme::Xterm => &XTERM,
+            Theme::Vga => &VGA,
+            Theme::Solarized => &SOLARIZED,
+            Theme::Tango => &TANGO,
+        };
+        Palette::from_base(base)
+    }
+}
+
+impl core::str::FromStr for Theme {
+    type Err = PaletteError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "xterm" => Ok(Theme::Xterm),
+            "vga" => Ok(Theme::Vga),
+            "solarized" => Ok(Theme::Solarized),
+            "tango" => Ok(Theme::Tango),
+            _ => Err(PaletteError::UnknownTheme(s.to_string())),
+        }
+    }
+}
+
+/// A complete set of 256 terminal colors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    colors: [Rgb; 256],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Theme::default().palette()
+    }
+}
+
+impl Palette {
+    /// Create a palette from a set of base colors, starting at index 0. Colors 16..=231 are the
+    /// standard 6x6x6 color cube and colors 232..=255 are the standard grayscale ramp, unless
+    /// overridden by the base colors.
+    #[must_use]
+    pub fn from_base(base: &[Rgb]) -> Self {
+        let mut colors = [Rgb::default(); 256];
+        for (i, color) in colors.iter_mut().enumerate() {
+            *color = match i {
+                0..=15 => XTERM[i],
+                16..=231 => {
+                    let n = i - 16;
+                    Rgb(
+                        CUBE_LEVELS[n / 36],
+                        CUBE_LEVELS[(n / 6) % 6],
+                        CUBE_LEVELS[n % 6],
+                    )
+                }
+                _ => {
+                    #[allow(clippy::cast_possible_truncation)]
+                    let level = 8 + 10 * (i - 232) as u8;
+                    Rgb(level, level, level)
+                }
+            };
+        }
+        for (color, base) in colors.iter_mut().zip(base) {
+            *color = *base;
+        }
+        Palette { colors }
+    }
+
+    /// Parse a palette containing one `#rrggbb` color per line, starting at index 0. Empty lines
+    /// are ignored. Any colors not given are taken from [`Palette::from_base`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error when a color can't be parsed or more than 256 colors are given.
+    pub fn parse(input: &str) -> Result<Self, PaletteError> {
+        let colors = input
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<Rgb>, _>>()?;
+        if colors.len() > 256 {
+            return Err(PaletteError::TooManyColors);
+        }
+        Ok(Palette::from_base(&colors))
+    }
+
+    /// Load a palette from a file, using the format from [`Palette::parse`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error when the file can't be read or parsed.
+    #[cfg(feature = "std")]
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, PaletteError> {
+        Palette::parse(std::fs::read_to_string(path)?.as_str())
+    }
+
+    /// Returns the color at the given index.
+    #[must_use]
+    pub fn color(&self, index: u8) -> Rgb {
+        self.colors[index as usize]
+    }
+
+    /// Returns the index of the color closest to the given color, weighting each channel by how
+    /// sensitive the eye is to it so that the hue is kept where possible.
+    #[must_use]
+    pub fn nearest(&self, color: Rgb) -> u8 {
+        let distance = |other: &Rgb| {
+            // The "redmean" approximation of perceptual distance
+            let mean = (i32::from(color.0) + i32::from(other.0)) / 2;
+            let [r, g, b] = [
+                i32::from(color.0) - i32::from(other.0),
+                i32::from(color.1) - i32::from(other.1),
+                i32::from(color.2) - i32::from(other.2),
+            ];
+            (((512 + mean) * r * r) >> 8) + 4 * g * g + (((767 - mean) * b * b) >> 8)
+        };
+        let (index, _) = self
+            .colors
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, other)| distance(other))
+            .expect("palettes always have 256 colors");
+        u8::try_from(index).expect("palettes have at most 256 colors")
+    }
+
+    /// Generate a `:root` block defining a `--terminal-color-{n}` CSS variable for every color.
+    #[must_use]
+    pub fn stylesheet(&self) -> String {
+        self.stylesheet_from(0)
+    }
+
+    /// Generate a `:root` block defining a `--terminal-color-{n}` CSS variable for the 240
+    /// extended colors only, for pages whose own theme defines the 16 base colors.
+    #[must_use]
+    pub fn extended_stylesheet(&self) -> String {
+        self.stylesheet_from(16)
+    }
+
+    /// Generate a `:root` block defining a CSS variable for every color from the given index.
+    fn stylesheet_from(&self, first: usize) -> String {
+        let mut output = vec![":root {".to_string()];
+        for (n, color) in self.colors.iter().enumerate().skip(first) {
+            output.push(format!("\t--terminal-color-{n}: {color};"));
+        }
+        output.push("}".to_string());
+        output.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generating_palette() {
+        let palette = Theme::Xterm.palette();
+        assert_eq!(palette.color(1), Rgb(0xcd, 0x00, 0x00));
+        assert_eq!(palette.color(16), Rgb(0, 0, 0));
+        assert_eq!(palette.color(196), Rgb(255, 0, 0));
+        assert_eq!(palette.color(231), Rgb(255, 255, 255));
+        assert_eq!(palette.color(232), Rgb(8, 8, 8));
+        assert_eq!(palette.color(255), Rgb(238, 238, 238));
+
+        // Only the base colors differ between themes
+        let vga = Theme::Vga.palette();
+        assert_eq!(vga.color(3), Rgb(0xaa, 0x55, 0x00));
+        assert_eq!(vga.colors[16..], palette.colors[16..]);
+    }
+
+    #[test]
+    fn measuring_contrast() {
+        let (black, white) = (Rgb(0, 0, 0), Rgb(255, 255, 255));
+        assert_eq!(black.relative_luminance(), 0.0);
+        assert_eq!(white.relative_luminance(), 1.0);
+        assert_eq!(black.contrast_ratio(&white), 21.0);
+        assert_eq!(white.contrast_ratio(&black), 21.0);
+        for color in [Rgb(0xcd, 0x00, 0x00), Rgb(0x12, 0x34, 0x56), Rgb(7, 7, 7)] {
+            let (hue, saturation, lightness) = color.to_hsl();
+            assert_eq!(Rgb::from_hsl(hue, saturation, lightness), color);
+        }
+    }
+
+    #[test]
+    fn adjusting_contrast() {
+        let background = Rgb(0xcb, 0xcb, 0xcb);
+        let yellow = Rgb(0xff, 0xff, 0x55);
+        let adjusted = yellow.with_contrast(&background, 4.5);
+        assert!(adjusted.contrast_ratio(&background) >= 4.5);
+        // Only the lightness changes
+        let (hue, saturation, lightness) = adjusted.to_hsl();
+        assert!((hue - 60.0).abs() < 1.0);
+        assert!(saturation > 0.95);
+        assert!(lightness < yellow.to_hsl().2);
+        // The change is as small as possible
+        assert!(adjusted.contrast_ratio(&background) < 4.6);
+
+        // Colors that already have enough contrast are kept
+        assert_eq!(
+            Rgb(0, 0, 0x80).with_contrast(&background, 4.5),
+            Rgb(0, 0, 0x80)
+        );
+        // Dark backgrounds lighten colors instead
+        let dark = Rgb(0x1c, 0x1c, 0x1c);
+        let adjusted = Rgb(0x00, 0x00, 0xee).with_contrast(&dark, 4.5);
+        assert!(adjusted.contrast_ratio(&dark) >= 4.5);
+        assert!(adjusted.to_hsl().2 > Rgb(0x00, 0x00, 0xee).to_hsl().2);
+        // An impossible minimum gives the highest contrast possible
+        assert_eq!(Rgb(9, 9, 9).with_contrast(&dark, 30.0), Rgb(255, 255, 255));
+    }
+
+    #[test]
+    fn finding_nearest_colors() {
+        let palette = Palette::default();
+        assert_eq!(palette.nearest(Rgb(0xcd, 0x00, 0x00)), 1);
+        assert_eq!(palette.nearest(Rgb(0xff, 0x80, 0x00)), 208);
+        assert_eq!(palette.nearest(Rgb(0x7f, 0x7f, 0x80)), 8);
+    }
+
+    #[test]
+    fn parsing_themes() {
+        assert_eq!("xterm".parse::<Theme>().unwrap(), Theme::Xterm);
+        assert_eq!("VGA".parse::<Theme>().unwrap(), Theme::Vga);
+        assert_eq!("solarized".parse::<Theme>().unwrap(), Theme::Solarized);
+        assert_eq!("tango".parse::<Theme>().unwrap(), Theme::Tango);
+        assert!(matches!(
+            "apprentice".parse::<Theme>(),
+            Err(PaletteError::UnknownTheme(_))
+        ));
+    }
+
+    #[test]
+    fn parsing_custom_palette() {
+        let palette = Palette::parse("#010203\n\n  #A0B0C0\n").unwrap();
+        assert_eq!(palette.color(0), Rgb(1, 2, 3));
+        assert_eq!(palette.color(1), Rgb(0xa0, 0xb0, 0xc0));
+        assert_eq!(palette.color(2), XTERM[2]);
+
+        assert!(matches!(
+            Palette::parse("#01020"),
+            Err(PaletteError::InvalidColor(_))
+        ));
+        assert!(matches!(
+            Palette::parse("010203"),
+            Err(PaletteError::InvalidColor(_))
+        ));
+        assert!(matches!(
+            Palette::parse("#000000\n".repeat(257).as_str()),
+            Err(PaletteError::TooManyColors)
+        ));
+    }
+
+    #[test]
+    fn generating_stylesheet() {
+        let stylesheet = Theme::Tango.palette().stylesheet();
+        let lines: Vec<_> = stylesheet.lines().collect();
+        assert_eq!(lines.len(), 258);
+        assert_eq!(lines[0], ":root {");
+        assert_eq!(lines[1], "\t--terminal-color-0: #2e3436;");
+        assert_eq!(lines[256], "\t--terminal-color-255: #eeeeee;");
+        assert_eq!(lines[257], "}");
+
+        let stylesheet = Theme::Tango.palette().extended_stylesheet();
+        let lines: Vec<_> = stylesheet.lines().collect();
+        assert_eq!(lines.len(), 242);
+        assert_eq!(lines[1], "\t--terminal-color-16: #000000;");
+        assert!(!stylesheet.contains("--terminal-color-0:"));
+        assert!(!stylesheet.contains("--terminal-color-15:"));
+    }
+}
@@ -0,0 +1,277 @@
+//! A tokenizer splitting ANSI terminal output into text and escape sequences.
+//!
+//! Unlike the `ansi-parser` crate, every [CSI] sequence is recognized regardless of its parameters, so
+//! that sequences such as `ESC [ 2 K` can be interpreted rather than being passed through as text.
+//!
+//! [CSI]: https://en.wikipedia.org/wiki/ANSI_escape_code#CSI_(Control_Sequence_Introducer)_sequences
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// A parameter of an SGR sequence, followed by any subparameters separated from it by colons, such
+/// as `4:3`. Omitted subparameters, such as the color space of `38:2::r:g:b`, are `None`.
+pub(crate) type SgrParam = Vec<Option<u16>>;
+
+/// A single piece of ANSI terminal output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Sequence<'a> {
+    /// Text, including any control characters other than escape.
+    Text(&'a str),
+    /// A Control Sequence Introducer sequence: `ESC [ params intermediates action`.
+    Csi {
+        params: &'a str,
+        intermediates: &'a str,
+        action: char,
+    },
+    /// An Operating System Command sequence, terminated by `BEL` or `ESC \`.
+    Osc(&'a str),
+    /// Any other escape sequence.
+    Escape(&'a str),
+}
+
+impl<'a> Sequence<'a> {
+    /// Returns the numeric parameters of a CSI sequence with the given action and no
+    /// intermediates, using 0 for empty parameters. Returns `None` for any other sequence, or if
+    /// any parameters aren't numeric.
+    pub(crate) fn csi_params(&self, expected_action: char) -> Option<Vec<u16>> {
+        let Sequence::Csi {
+            params,
+            intermediates: "",
+            action,
+        } = self
+        else {
+            return None;
+        };
+        if *action != expected_action {
+            return None;
+        }
+        if params.is_empty() {
+            return Some(vec![]);
+        }
+        params
+            .split(';')
+            .map(|param| match param {
+                "" => Some(0),
+                _ => param.parse().ok(),
+            })
+            .collect()
+    }
+
+    /// Returns the parameters of an SGR sequence, `ESC [ params m`, with their subparameters. An
+    /// empty set of parameters is equivalent to a reset, and an empty parameter is equivalent to 0.
+    /// Returns `None` for any other sequence, or if any parameters aren't numeric.
+    pub(crate) fn sgr_params(&self) -> Option<Vec<SgrParam>> {
+        let Sequence::Csi {
+            params,
+            intermediates: "",
+            action: 'm',
+        } = self
+        else {
+            return None;
+        };
+        if params.is_empty() {
+            return Some(vec![vec![Some(0)]]);
+        }
+        params
+            .split(';')
+            .map(|param| {
+                let mut parts = param.split(':');
+                let first = match parts.next() {
+                    Some("") | None => 0,
+                    Some(first) => first.parse().ok()?,
+                };
+                let mut param = vec![Some(first)];
+                for part in parts {
+                    param.push(match part {
+                        "" => None,
+                        _ => Some(part.parse().ok()?),
+                    });
+                }
+                Some(param)
+            })
+            .collect()
+    }
+
+    /// Returns the URI of an [OSC 8] hyperlink sequence, `ESC ] 8 ; params ; URI ST`. An empty
+    /// URI closes the current hyperlink and is returned as `Some(None)`. Returns `None` for any
+    /// other sequence.
+    ///
+    /// [OSC 8]: https://gist.github.com/egmontkob/eb114294efbcd5adb1944c9f3cb5feda
+    pub(crate) fn hyperlink(&self) -> Option<Option<&'a str>> {
+        let Sequence::Osc(data) = self else {
+            return None;
+        };
+        let (_params, uri) = data.strip_prefix("8;")?.split_once(';')?;
+        Some(Some(uri).filter(|uri| !uri.is_empty()))
+    }
+}
+
+/// Split the first [`Sequence`] from the input, returning the sequence and the remaining input.
+/// Returns `None` if the input is empty or starts with an incomplete escape sequence.
+pub(crate) fn split_sequence(input: &str) -> Option<(Sequence<'_>, &str)> {
+    let Some(rest) = input.strip_prefix('\x1b') else {
+        if input.is_empty() {
+            return None;
+        }
+        let end = input.find('\x1b').unwrap_or(input.len());
+        return Some((Sequence::Text(&input[..end]), &input[end..]));
+    };
+
+    match rest.chars().next()? {
+        '[' => {
+            let body = &rest[1..];
+            let params_end = body
+                .find(|c: char| !('\x30'..='\x3f').contains(&c))
+                .unwrap_or(body.len());
+            let intermediates_end = body[params_end..]
+                .find(|c: char| !('\x20'..='\x2f').contains(&c))
+                .map_or(body.len(), |i| i + params_end);
+            let action = body[intermediates_end..].chars().next()?;
+            let sequence = Sequence::Csi {
+                params: &body[..params_end],
+                intermediates: &body[params_end..intermediates_end],
+                action,
+            };
+            Some((sequence, &body[intermediates_end + action.len_utf8()..]))
+        }
+        ']' => {
+            let body = &rest[1..];
+            let end = body.find(['\x07', '\x1b'])?;
+            let remaining = match body[end..].strip_prefix("\x1b\\") {
+                Some(remaining) => remaining,
+                None if body[end..].starts_with('\x07') => &body[end + 1..],
+                // an escape that isn't a string terminator aborts the command
+                None if body.len() > end + 1 => &body[end..],
+                None => return None,
+            };
+            Some((Sequence::Osc(&body[..end]), remaining))
+        }
+        _ => {
+            // nF sequences have any number of intermediate bytes before the final byte
+            let end = rest.find(|c: char| !('\x20'..='\x2f').contains(&c))?;
+            let final_length = rest[end..].chars().next()?.len_utf8();
+            let length = 1 + end + final_length;
+            Some((Sequence::Escape(&input[..length]), &input[length..]))
+        }
+    }
+}
+
+/// An iterator over every [`Sequence`] of an input. An incomplete escape sequence at the end of
+/// the input is discarded.
+pub(crate) struct Sequences<'a> {
+    input: &'a str,
+}
+
+impl<'a> Iterator for Sequences<'a> {
+    type Item = Sequence<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (sequence, rest) = split_sequence(self.input)?;
+        self.input = rest;
+        Some(sequence)
+    }
+}
+
+/// Split the input into every [`Sequence`].
+pub(crate) fn parse(input: &str) -> Sequences<'_> {
+    Sequences { input }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing_sequences() {
+        let input = "a\x1b[1;31mb\x1b[2K\r\x1b]8;;http://x\x07c\x1b]0;t\x1b\\\x1b(Bd\x1b[?25l";
+        let sequences: Vec<_> = parse(input).collect();
+        assert_eq!(
+            sequences,
+            vec![
+                Sequence::Text("a"),
+                Sequence::Csi {
+                    params: "1;31",
+                    intermediates: "",
+                    action: 'm'
+                },
+                Sequence::Text("b"),
+                Sequence::Csi {
+                    params: "2",
+                    intermediates: "",
+                    action: 'K'
+                },
+                Sequence::Text("\r"),
+                Sequence::Osc("8;;http://x"),
+                Sequence::Text("c"),
+                Sequence::Osc("0;t"),
+                Sequence::Escape("\x1b(B"),
+                Sequence::Text("d"),
+                Sequence::Csi {
+                    params: "?25",
+                    intermediates: "",
+                    action: 'l'
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parsing_incomplete_sequences() {
+        for input in [
+            "\x1b",
+            "\x1b[",
+            "\x1b[1;3",
+            "\x1b]8;;http",
+            "\x1b]8;;\x1b",
+            "\x1b(",
+        ] {
+            assert_eq!(
+                split_sequence(input),
+                None,
+                "{input:?} shouldn't be complete"
+            );
+        }
+        assert_eq!(
+            parse("a\x1b[1").collect::<Vec<_>>(),
+            vec![Sequence::Text("a")]
+        );
+    }
+
+    #[test]
+    fn parsing_params() {
+        let (sequence, _) = split_sequence("\x1b[;5H").unwrap();
+        assert_eq!(sequence.csi_params('H'), Some(vec![0, 5]));
+        assert_eq!(sequence.csi_params('m'), None);
+
+        let (sequence, _) = split_sequence("\x1b[m").unwrap();
+        assert_eq!(sequence.sgr_params(), Some(vec![vec![Some(0)]]));
+
+        let (sequence, _) = split_sequence("\x1b[;4:3;38:2::1:2:3m").unwrap();
+        assert_eq!(
+            sequence.sgr_params(),
+            Some(vec![
+                vec![Some(0)],
+                vec![Some(4), Some(3)],
+                vec![Some(38), Some(2), None, Some(1), Some(2), Some(3)],
+            ])
+        );
+
+        let (sequence, _) = split_sequence("\x1b[1;xm").unwrap();
+        assert_eq!(sequence.sgr_params(), None);
+
+        let (sequence, _) = split_sequence("\x1b[?25l").unwrap();
+        assert_eq!(sequence.csi_params('l'), None);
+    }
+
+    #[test]
+    fn parsing_hyperlinks() {
+        let (sequence, _) = split_sequence("\x1b]8;id=a;http://x/;y\x07").unwrap();
+        assert_eq!(sequence.hyperlink(), Some(Some("http://x/;y")));
+
+        let (sequence, _) = split_sequence("\x1b]8;;\x1b\\").unwrap();
+        assert_eq!(sequence.hyperlink(), Some(None));
+
+        let (sequence, _) = split_sequence("\x1b]0;title\x07").unwrap();
+        assert_eq!(sequence.hyperlink(), None);
+    }
+}
@@ -0,0 +1,278 @@
+//! Renderers converting [`StyledSpan`]s into documents, so that the parsing of ANSI terminal
+//! output can be reused for formats other than HTML.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use opaque_ansi::parse_styled;
+//! use opaque_ansi::render::{PlainTextRenderer, Renderer};
+//!
+//! let spans = parse_styled("\x1b[1;31merror\x1b[0m: oops");
+//! assert_eq!(PlainTextRenderer.render(&spans), "error: oops");
+//! ```
+
+use alloc::string::{String, ToString};
+use alloc::{format, vec};
+
+use crate::palette::Palette;
+use crate::writer::render_blocks;
+use crate::{FileLinkPolicy, RenderOptions, Style, StyledSpan};
+
+/// Converts spans of styled text into a document.
+pub trait Renderer {
+    /// Render every span, in order, into a single document.
+    fn render(&self, spans: &[StyledSpan]) -> String;
+}
+
+/// Render every line of every span using the given function, separating lines with the given
+/// line break. The function is never called with empty text or text containing a newline.
+fn render_lines(
+    spans: &[StyledSpan],
+    line_break: &str,
+    mut render_text: impl FnMut(&Style, &str) -> String,
+) -> String {
+    let mut output = String::new();
+    for span in spans {
+        for (i, text) in span.text.split('\n').enumerate() {
+            if i > 0 {
+                output.push_str(line_break);
+            }
+            if !text.is_empty() {
+                output.push_str(&render_text(&span.style, text));
+            }
+        }
+    }
+    output
+}
+
+/// Renders spans as HTML, in the same way as [`crate::rewrite_ansi_to_html_with`].
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct HtmlRenderer {
+    pub options: RenderOptions,
+}
+
+impl Renderer for HtmlRenderer {
+    fn render(&self, spans: &[StyledSpan]) -> String {
+        let blocks = spans.iter().map(|span| (&span.style, span.text.as_str()));
+        let html = render_blocks(blocks, &self.options);
+        format!("<pre class=\"ansi_output\"><code>{html}</code></pre>")
+    }
+}
+
+/// Renders spans as plain text, removing every style.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlainTextRenderer;
+
+impl Renderer for PlainTextRenderer {
+    fn render(&self, spans: &[StyledSpan]) -> String {
+        spans.iter().map(|span| span.text.as_str()).collect()
+    }
+}
+
+/// Renders spans as LaTeX within an `alltt` environment, which keeps whitespace while still
+/// interpreting commands. Colors use `\textcolor` and `\colorbox` from the `xcolor` package and
+/// strikethrough uses `\sout` from the `ulem` package. Dim text, overlines, and hyperlinks aren't
+/// rendered.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct LatexRenderer {
+    /// The palette used for the 256 standard terminal colors.
+    pub palette: Palette,
+}
+
+impl LatexRenderer {
+    /// Render a line of text as LaTeX using commands for the style.
+    fn render_text(&self, style: &Style, text: &str) -> String {
+        // Only backslashes and braces are special within alltt
+        let mut output = String::new();
+        for character in text.chars() {
+            match character {
+                '\\' => output.push_str("\\textbackslash{}"),
+                '{' => output.push_str("\\{"),
+                '}' => output.push_str("\\}"),
+                _ => output.push(character),
+            }
+        }
+
+        let (color, background_color) = style.resolved_colors();
+        let commands = [
+            (style.hidden, "\\phantom".to_string()),
+            (style.bold, "\\textbf".to_string()),
+            (style.italic, "\\textit".to_string()),
+            (
+                style.underline || style.double_underline,
+                "\\underline".to_string(),
+            ),
+            (style.strikethrough, "\\sout".to_string()),
+        ];
+        let colors = [
+            (color.rgb(&self.palette), "\\textcolor"),
+            (background_color.rgb(&self.palette), "\\colorbox"),
+        ];
+        let commands = commands
+            .into_iter()
+            .filter(|(enabled, _)| *enabled)
+            .map(|(_, command)| command)
+            .chain(colors.into_iter().filter_map(|(color, command)| {
+                let hex = color?.to_string();
+                Some(format!("{command}[HTML]{{{}}}", hex[1..].to_uppercase()))
+            }));
+        for command in commands {
+            output = format!("{command}{{{output}}}");
+        }
+        output
+    }
+}
+
+impl Renderer for LatexRenderer {
+    fn render(&self, spans: &[StyledSpan]) -> String {
+        let latex = render_lines(spans, "\n", |style, text| self.render_text(style, text));
+        format!("\\begin{{alltt}}\n{latex}\n\\end{{alltt}}")
+    }
+}
+
+/// Renders spans as Markdown, using inline HTML for styles and colors so that the output doesn't
+/// depend on where emphasis may start or end. Every line ends with a hard line break and repeated
+/// spaces are kept as non-breaking spaces, so the output is a single paragraph.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct MarkdownRenderer {
+    /// The palette used for the 256 standard terminal colors.
+    pub palette: Palette,
+}
+
+impl MarkdownRenderer {
+    /// Render a line of text as Markdown using inline HTML tags for the style.
+    fn render_text(&self, style: &Style, text: &str) -> String {
+        let mut output = String::new();
+        let mut previous = ' ';
+        for character in text.chars() {
+            match character {
+                ' ' if previous == ' ' => output.push_str("&nbsp;"),
+                // Any ASCII punctuation may be escaped, so there's no need to be selective
+                c if c.is_ascii_punctuation() => {
+                    output.push('\\');
+                    output.push(c);
+                }
+                c => output.push(c),
+            }
+            previous = character;
+        }
+
+        let mut tags = vec![];
+        let attributes = [
+            (style.bold, "strong"),
+            (style.italic, "em"),
+            (style.underline || style.double_underline, "u"),
+            (style.strikethrough, "s"),
+        ];
+        for (enabled, tag) in attributes {
+            if enabled {
+                tags.push((format!("<{tag}>"), format!("</{tag}>")));
+            }
+        }
+
+        let (color, background_color) = style.resolved_colors();
+        let mut styles = vec![];
+        if let Some(color) = color.rgb(&self.palette) {
+            styles.push(format!("color: {color}"));
+        }
+        if let Some(background_color) = background_color.rgb(&self.palette) {
+            styles.push(format!("background-color: {background_color}"));
+        }
+        if style.hidden {
+            styles.push("visibility: hidden".to_string());
+        }
+        if !styles.is_empty() {
+            tags.push((
+                format!("<span style=\"{}\">", styles.join("; ")),
+                "</span>".to_string(),
+            ));
+        }
+
+        let hyperlink = style
+            .hyperlink
+            .as_deref()
+            .and_then(|url| FileLinkPolicy::Keep.resolve(url));
+        if let Some(url) = hyperlink {
+            let url = html_escape::encode_double_quoted_attribute(url.as_str());
+            tags.push((format!("<a href=\"{url}\">"), "</a>".to_string()));
+        }
+
+        for (opening_tag, closing_tag) in tags {
+            output = format!("{opening_tag}{output}{closing_tag}");
+        }
+        output
+    }
+}
+
+impl Renderer for MarkdownRenderer {
+    fn render(&self, spans: &[StyledSpan]) -> String {
+        render_lines(spans, "\\\n", |style, text| self.render_text(style, text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_styled;
+
+    static INPUT: &str = "\x1b[1;31merror\x1b[0m: {x}\n  \x1b[4;44mnote\x1b[0m";
+
+    #[test]
+    fn parsing_styled_spans() {
+        let spans = parse_styled("\x1b[1ma\x1b[1mb\x1b[0m\x1b[2m\x1b[0mc");
+        let bold = Style {
+            bold: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            spans,
+            [
+                StyledSpan {
+                    style: bold,
+                    text: "ab".to_string()
+                },
+                StyledSpan {
+                    style: Style::default(),
+                    text: "c".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rendering_html() {
+        let spans = parse_styled(INPUT);
+        assert_eq!(
+            HtmlRenderer::default().render(&spans),
+            crate::rewrite_ansi_to_html(INPUT)
+        );
+    }
+
+    #[test]
+    fn rendering_plain_text() {
+        let spans = parse_styled(INPUT);
+        assert_eq!(PlainTextRenderer.render(&spans), "error: {x}\n  note");
+    }
+
+    #[test]
+    fn rendering_latex() {
+        let spans = parse_styled(INPUT);
+        assert_eq!(
+            LatexRenderer::default().render(&spans),
+            "\\begin{alltt}\n\
+             \\textcolor[HTML]{CD0000}{\\textbf{error}}: \\{x\\}\n  \
+             \\colorbox[HTML]{0000EE}{\\underline{note}}\n\
+             \\end{alltt}"
+        );
+    }
+
+    #[test]
+    fn rendering_markdown() {
+        let spans = parse_styled(INPUT);
+        assert_eq!(
+            MarkdownRenderer::default().render(&spans),
+            "<span style=\"color: #cd0000\"><strong>error</strong></span>\\: \\{x\\}\\\n\
+             &nbsp;&nbsp;<span style=\"background-color: #0000ee\"><u>note</u></span>"
+        );
+    }
+}
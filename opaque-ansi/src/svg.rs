@@ -0,0 +1,331 @@
+//! A renderer producing standalone SVG images of ANSI terminal output, for embedding output in
+//! places where HTML isn't allowed.
+//!
+//! Output is always replayed into a virtual terminal, so that every character can be placed on a
+//! grid of fixed-width cells. Wide characters, such as most emoji, are assumed to take a single
+//! cell.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+
+use crate::palette::{Palette, Rgb};
+use crate::{number, parse_blocks, RenderOptions, Style};
+
+/// The width of a cell relative to the font size, which most monospace fonts are close to.
+const CELL_WIDTH: f64 = 0.6;
+
+/// The height of a line relative to the font size.
+const LINE_HEIGHT: f64 = 1.2;
+
+/// The distance from the top of a line to the baseline of its text, relative to the font size.
+const BASELINE: f64 = 0.95;
+
+/// The space between the text and the edges of the image, relative to the font size.
+const PADDING: f64 = 1.0;
+
+/// The height of the title bar of the window chrome, relative to the font size.
+const TITLE_BAR_HEIGHT: f64 = 2.0;
+
+/// The colors of the close, minimize, and maximize buttons of the window chrome.
+static BUTTON_COLORS: [Rgb; 3] = [
+    Rgb(0xff, 0x5f, 0x56),
+    Rgb(0xff, 0xbd, 0x2e),
+    Rgb(0x27, 0xc9, 0x3f),
+];
+
+/// Options for rendering an ANSI terminal output to SVG.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SvgOptions {
+    /// The value of the `font-family` attribute, which should only contain monospace fonts.
+    pub font_family: String,
+    /// The font size, in pixels.
+    pub font_size: u32,
+    /// The palette used for the 256 standard terminal colors.
+    pub palette: Palette,
+    /// The color of text using the default foreground color.
+    pub foreground: Rgb,
+    /// The color of the image behind text using the default background color.
+    pub background: Rgb,
+    /// Draw a window title bar above the output.
+    pub window_chrome: bool,
+    /// The title shown in the window title bar. Only used when [`SvgOptions::window_chrome`] is
+    /// set.
+    pub title: Option<String>,
+    /// The width of the virtual terminal, after which text wraps to the next line. When `None`,
+    /// the image is as wide as the longest line.
+    pub terminal_width: Option<usize>,
+    /// The height of the virtual terminal, which limits erasing the screen to the last lines of
+    /// the output. When `None`, the terminal is 24 rows tall.
+    pub terminal_height: Option<usize>,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        SvgOptions {
+            font_family: "ui-monospace, SFMono-Regular, Menlo, Consolas, monospace".to_string(),
+            font_size: 14,
+            palette: Palette::default(),
+            foreground: Rgb(0xbc, 0xbc, 0xbc),
+            background: Rgb(0x1c, 0x1c, 0x1c),
+            window_chrome: false,
+            title: None,
+            terminal_width: None,
+            terminal_height: None,
+        }
+    }
+}
+
+/// Returns the foreground color and background color, if any, of text formatted with the state.
+fn resolve_colors(state: &Style, options: &SvgOptions) -> (Rgb, Option<Rgb>) {
+    let (color, background_color) = state.resolved_colors();
+    let color = color.rgb(&options.palette);
+    let background_color = background_color.rgb(&options.palette);
+    if state.inverse {
+        (
+            color.unwrap_or(options.background),
+            Some(background_color.unwrap_or(options.foreground)),
+        )
+    } else {
+        (color.unwrap_or(options.foreground), background_color)
+    }
+}
+
+/// Returns the attributes of a `<tspan>` representing the formatting of the text.
+fn build_attributes(state: &Style, color: Rgb, options: &SvgOptions) -> String {
+    let mut attributes = String::new();
+
+    if color != options.foreground {
+        attributes.push_str(&format!(" fill=\"{color}\""));
+    }
+
+    if state.bold {
+        attributes.push_str(" font-weight=\"bold\"");
+    }
+
+    if state.italic {
+        attributes.push_str(" font-style=\"italic\"");
+    }
+
+    if state.dim {
+        attributes.push_str(" fill-opacity=\"0.5\"");
+    }
+
+    let decorations = [
+        (state.underline || state.double_underline, "underline"),
+        (state.strikethrough, "line-through"),
+        (state.overline, "overline"),
+    ];
+    let lines: Vec<&str> = decorations
+        .into_iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, line)| line)
+        .collect();
+    if !lines.is_empty() {
+        attributes.push_str(&format!(" text-decoration=\"{}\"", lines.join(" ")));
+    }
+
+    attributes
+}
+
+/// Split an ANSI terminal output into lines of blocks of text, alongside the state each block of
+/// text should be formatted with.
+fn parse_lines(input: &str, options: &SvgOptions) -> Vec<Vec<(Style, String)>> {
+    let render_options = RenderOptions {
+        emulate_terminal: true,
+        terminal_width: options.terminal_width,
+        terminal_height: options.terminal_height,
+        ..Default::default()
+    };
+
+    let mut lines = vec![vec![]];
+    for (state, text) in parse_blocks(input, &render_options) {
+        for (i, part) in text.split('\n').enumerate() {
+            if i > 0 {
+                lines.push(vec![]);
+            }
+            if !part.is_empty() {
+                let line = lines.last_mut().expect("there is always a line");
+                line.push((state.clone(), part.to_string()));
+            }
+        }
+    }
+
+    // A trailing newline doesn't start another visible line
+    if lines.len() > 1 && lines.last().is_some_and(Vec::is_empty) {
+        lines.pop();
+    }
+    lines
+}
+
+/// Render an ANSI terminal output to a standalone SVG image, using a grid of monospace text with
+/// colors taken from the given [`SvgOptions`].
+#[cfg_attr(feature = "tracing", tracing::instrument(skip(input)))]
+pub fn rewrite_ansi_to_svg(input: &str, options: &SvgOptions) -> String {
+    let lines = parse_lines(input, options);
+    let columns = options.terminal_width.unwrap_or_else(|| {
+        lines
+            .iter()
+            .map(|line| line.iter().map(|(_, text)| text.chars().count()).sum())
+            .max()
+            .unwrap_or(0)
+    });
+
+    let font_size = f64::from(options.font_size);
+    let cell_width = font_size * CELL_WIDTH;
+    let line_height = font_size * LINE_HEIGHT;
+    let padding = font_size * PADDING;
+    let title_bar_height = if options.window_chrome {
+        font_size * TITLE_BAR_HEIGHT
+    } else {
+        0.0
+    };
+    #[allow(clippy::cast_precision_loss)]
+    let (width, height) = (
+        columns as f64 * cell_width + 2.0 * padding,
+        title_bar_height + lines.len() as f64 * line_height + 2.0 * padding,
+    );
+
+    let mut svg = vec![format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+         viewBox=\"0 0 {width} {height}\" font-family=\"{font_family}\" \
+         font-size=\"{font_size}\">",
+        width = number(width),
+        height = number(height),
+        font_family = html_escape::encode_double_quoted_attribute(&options.font_family),
+        font_size = options.font_size,
+    )];
+    let radius = if options.window_chrome {
+        number(font_size * 0.5)
+    } else {
+        "0".to_string()
+    };
+    svg.push(format!(
+        "<rect width=\"100%\" height=\"100%\" rx=\"{radius}\" fill=\"{}\"/>",
+        options.background
+    ));
+
+    if options.window_chrome {
+        let center = title_bar_height / 2.0;
+        for (i, color) in BUTTON_COLORS.iter().enumerate() {
+            #[allow(clippy::cast_precision_loss)]
+            let x = padding + i as f64 * font_size * 1.2;
+            svg.push(format!(
+                "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{color}\"/>",
+                number(x),
+                number(center),
+                number(font_size * 0.4),
+            ));
+        }
+        if let Some(title) = &options.title {
+            svg.push(format!(
+                "<text x=\"50%\" y=\"{}\" text-anchor=\"middle\" dominant-baseline=\"middle\" \
+                 fill=\"{}\" fill-opacity=\"0.7\">{}</text>",
+                number(center),
+                options.foreground,
+                html_escape::encode_text(title),
+            ));
+        }
+    }
+
+    svg.push(format!("<g fill=\"{}\">", options.foreground));
+    for (row, line) in lines.iter().enumerate() {
+        #[allow(clippy::cast_precision_loss)]
+        let y = title_bar_height + padding + row as f64 * line_height;
+        let mut column = 0;
+        let mut spans = vec![];
+        for (state, text) in line {
+            let length = text.chars().count();
+            #[allow(clippy::cast_precision_loss)]
+            let (x, run_width) = (
+                padding + column as f64 * cell_width,
+                length as f64 * cell_width,
+            );
+            column += length;
+
+            let (color, background_color) = resolve_colors(state, options);
+            if let Some(background_color) = background_color {
+                svg.push(format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{background_color}\"/>",
+                    number(x),
+                    number(y),
+                    number(run_width),
+                    number(line_height),
+                ));
+            }
+
+            let invisible = text.chars().all(char::is_whitespace) && state.is_foreground_only();
+            if state.hidden || invisible {
+                continue;
+            }
+            spans.push(format!(
+                "<tspan x=\"{}\"{}>{}</tspan>",
+                number(x),
+                build_attributes(state, color, options),
+                html_escape::encode_text(text),
+            ));
+        }
+
+        if !spans.is_empty() {
+            svg.push(format!(
+                "<text y=\"{}\" xml:space=\"preserve\">{}</text>",
+                number(y + font_size * BASELINE),
+                spans.join(""),
+            ));
+        }
+    }
+    svg.push("</g>".to_string());
+    svg.push("</svg>".to_string());
+
+    svg.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rendering_svg() {
+        let options = SvgOptions {
+            font_size: 10,
+            ..Default::default()
+        };
+        let svg = rewrite_ansi_to_svg("\x1b[1;31mred\x1b[0m \x1b[44mblue\x1b[0m\nx\n", &options);
+        assert!(svg.starts_with(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"68\" height=\"44\" \
+             viewBox=\"0 0 68 44\""
+        ));
+        assert!(svg.contains("<g fill=\"#bcbcbc\">"));
+        assert!(svg.contains(
+            "<text y=\"19.5\" xml:space=\"preserve\">\
+             <tspan x=\"10\" fill=\"#cd0000\" font-weight=\"bold\">red</tspan>\
+             <tspan x=\"34\">blue</tspan></text>"
+        ));
+        assert!(
+            svg.contains("<rect x=\"34\" y=\"10\" width=\"24\" height=\"12\" fill=\"#0000ee\"/>")
+        );
+        assert!(svg
+            .contains("<text y=\"31.5\" xml:space=\"preserve\"><tspan x=\"10\">x</tspan></text>"));
+        assert!(svg.ends_with("</g>\n</svg>"));
+    }
+
+    #[test]
+    fn rendering_svg_with_chrome() {
+        let options = SvgOptions {
+            font_size: 10,
+            window_chrome: true,
+            title: Some("cargo <build>".to_string()),
+            terminal_width: Some(20),
+            palette: crate::palette::Theme::Vga.palette(),
+            ..Default::default()
+        };
+        let svg = rewrite_ansi_to_svg("\x1b[7mok\x1b[0m", &options);
+        assert!(svg.contains("width=\"140\" height=\"52\""));
+        assert!(svg.contains("<circle cx=\"10\" cy=\"10\" r=\"4\" fill=\"#ff5f56\"/>"));
+        assert!(svg.contains(">cargo &lt;build&gt;</text>"));
+        assert!(
+            svg.contains("<rect x=\"10\" y=\"30\" width=\"12\" height=\"12\" fill=\"#bcbcbc\"/>")
+        );
+        assert!(svg.contains("<tspan x=\"10\" fill=\"#1c1c1c\">ok</tspan>"));
+    }
+}
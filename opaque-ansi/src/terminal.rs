@@ -0,0 +1,450 @@
+//! A virtual terminal, replaying ANSI terminal output into a grid of styled cells so that the
+//! rendered output matches what was displayed rather than every block of text that was written.
+//!
+//! The following are interpreted, with every other escape sequence being ignored:
+//!
+//! * Carriage returns (`\r`), backspaces (`\b`), tabs (`\t`), and newlines (`\n`), which are
+//!   interpreted as a carriage return and line feed.
+//! * CUU, CUD, CUF, and CUB (`ESC [ n A` through `ESC [ n D`), moving the cursor.
+//! * CHA (`ESC [ n G`) and CUP (`ESC [ row ; col H`), moving the cursor to a position. Rows are
+//!   counted from the first line within the height of the terminal.
+//! * EL (`ESC [ n K`) and ED (`ESC [ n J`), erasing part of a line or the screen. ED 2 erases the
+//!   lines within the height of the terminal, and ED 3 erases every line.
+//! * SGR (`ESC [ params m`), changing the formatting of following text.
+//! * OSC 8 (`ESC ] 8 ; params ; URI ST`), changing the hyperlink of following text.
+//!
+//! The grid has no fixed height, so every line that was written is kept as scrollback.
+
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::parser::Sequence;
+use crate::Style;
+
+/// The interval between tab stops.
+const TAB_WIDTH: usize = 8;
+
+/// The number of lines above the cursor that are kept. Lines further above the cursor are
+/// considered final, as no terminal is tall enough for the cursor to move back to them.
+const SCROLLBACK: usize = 1000;
+
+/// The height of the terminal when no height is given, which is the height of a VT100.
+const DEFAULT_ROWS: usize = 24;
+
+/// The furthest column the cursor can be moved to when the terminal has no width. Writing text can
+/// still make lines longer, but a single sequence can't create an arbitrarily long line.
+const MAX_COLUMNS: usize = 1024;
+
+/// The furthest row the cursor can be moved to by cursor movement sequences. Newlines can still
+/// move the cursor further, but a single sequence can't create arbitrarily many lines.
+const MAX_ROWS: usize = SCROLLBACK;
+
+/// A single character of the grid and the formatting it was written with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cell {
+    character: char,
+    state: Style,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell {
+            character: ' ',
+            state: Style::default(),
+        }
+    }
+}
+
+/// A grid of cells and a cursor, with lines being created as the cursor moves to them.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Terminal {
+    lines: Vec<Vec<Cell>>,
+    row: usize,
+    column: usize,
+    width: Option<usize>,
+    height: Option<usize>,
+    state: Style,
+}
+
+impl Terminal {
+    /// Create an empty terminal. When a width is given, text wraps to the next line after
+    /// reaching the width. The height limits which lines erasing the screen erases, and is
+    /// [`DEFAULT_ROWS`] when not given.
+    pub(crate) fn new(width: Option<usize>, height: Option<usize>) -> Self {
+        Terminal {
+            lines: vec![vec![]],
+            width: width.filter(|width| *width > 0),
+            height: height.filter(|height| *height > 0),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the current line, creating any lines up to and including the current line.
+    fn line(&mut self) -> &mut Vec<Cell> {
+        if self.lines.len() <= self.row {
+            self.lines.resize_with(self.row + 1, Vec::new);
+        }
+        &mut self.lines[self.row]
+    }
+
+    /// Returns the index of the first line within the height of the terminal, counting the
+    /// cursor's line even if nothing has been written to it.
+    fn screen_top(&self) -> usize {
+        let bottom = self.lines.len().max(self.row + 1);
+        bottom.saturating_sub(self.height.unwrap_or(DEFAULT_ROWS))
+    }
+
+    /// Move the cursor to the given column, clamped to the width of the terminal, or to
+    /// [`MAX_COLUMNS`] unless the cursor is already further than that.
+    fn set_column(&mut self, column: usize) {
+        self.column = match self.width {
+            Some(width) => column.min(width - 1),
+            None => column.min(self.column.max(MAX_COLUMNS - 1)),
+        };
+    }
+
+    /// Move the cursor to the given row, clamped to [`MAX_ROWS`] unless the cursor is already
+    /// further than that.
+    fn set_row(&mut self, row: usize) {
+        self.row = row.min(self.row.max(MAX_ROWS - 1));
+    }
+
+    /// Write a character to the current position, advancing the cursor.
+    fn write(&mut self, character: char) {
+        if self.width.is_some_and(|width| self.column >= width) {
+            self.row += 1;
+            self.column = 0;
+        }
+        let column = self.column;
+        let cell = Cell {
+            character,
+            state: self.state.clone(),
+        };
+        let line = self.line();
+        if line.len() <= column {
+            line.resize_with(column + 1, Cell::default);
+        }
+        line[column] = cell;
+        // Writing to the last column leaves the cursor past the end, wrapping on the next write
+        self.column += 1;
+    }
+
+    /// Interpret a control character.
+    fn control(&mut self, character: char) {
+        match character {
+            '\n' => {
+                self.row += 1;
+                self.column = 0;
+                self.line();
+            }
+            '\r' => self.column = 0,
+            '\x08' => self.set_column(self.column.saturating_sub(1)),
+            '\t' => self.set_column((self.column / TAB_WIDTH + 1) * TAB_WIDTH),
+            _ => (),
+        }
+    }
+
+    /// Erase part of the current line: from the cursor to the end for 0, from the start to the
+    /// cursor for 1, and the whole line for 2.
+    fn erase_line(&mut self, mode: u16) {
+        let column = self.column;
+        let line = self.line();
+        match mode {
+            0 => line.truncate(column),
+            1 => {
+                let end = (column + 1).min(line.len());
+                line[..end].fill(Cell::default());
+            }
+            2 => line.clear(),
+            _ => (),
+        }
+    }
+
+    /// Erase part of the screen: from the cursor to the end for 0, from the start to the cursor
+    /// for 1, the last lines within the height of the terminal for 2, and every line including
+    /// the scrollback for 3.
+    fn erase_display(&mut self, mode: u16) {
+        match mode {
+            0 => {
+                self.erase_line(0);
+                self.lines.truncate(self.row + 1);
+            }
+            1 => {
+                self.erase_line(1);
+                for line in &mut self.lines[..self.row] {
+                    line.clear();
+                }
+            }
+            2 => {
+                let start = self.screen_top().min(self.lines.len());
+                for line in &mut self.lines[start..] {
+                    line.clear();
+                }
+            }
+            3 => {
+                for line in &mut self.lines {
+                    line.clear();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Interpret a sequence, updating the grid, cursor, and formatting state.
+    pub(crate) fn feed(&mut self, sequence: &Sequence) {
+        if let Sequence::Text(text) = sequence {
+            for character in text.chars() {
+                if character.is_control() {
+                    self.control(character);
+                } else {
+                    self.write(character);
+                }
+            }
+            return;
+        }
+
+        if let Some(params) = sequence.sgr_params() {
+            self.state = self.state.clone_from_params(&params);
+            return;
+        }
+
+        if let Some(hyperlink) = sequence.hyperlink() {
+            self.state.hyperlink = hyperlink.map(String::from);
+            return;
+        }
+
+        let Sequence::Csi { action, .. } = sequence else {
+            return;
+        };
+        let Some(params) = sequence.csi_params(*action) else {
+            return;
+        };
+        let first = params.first().copied().unwrap_or(0);
+        // Movement counts and positions treat 0 as 1
+        let count = usize::from(first.max(1));
+
+        match action {
+            'A' => self.row = self.row.saturating_sub(count),
+            'B' => self.set_row(self.row.saturating_add(count)),
+            'C' => self.set_column(self.column.saturating_add(count)),
+            'D' => self.set_column(self.column.saturating_sub(count)),
+            'G' => self.set_column(count - 1),
+            'H' | 'f' => {
+                let column = params.get(1).copied().unwrap_or(0).max(1);
+                self.set_row(self.screen_top() + count - 1);
+                self.set_column(usize::from(column) - 1);
+            }
+            'J' => self.erase_display(first),
+            'K' => self.erase_line(first),
+            _ => (),
+        }
+    }
+
+    /// Remove and return lines too far above the cursor to be changed, as blocks of text alongside
+    /// the state each block of text should be formatted with. Every line is followed by an
+    /// unformatted newline. This bounds the memory used when replaying long outputs.
+    pub(crate) fn take_final_lines(&mut self) -> Vec<(Style, String)> {
+        let count = self.row.saturating_sub(SCROLLBACK);
+        if count == 0 {
+            return vec![];
+        }
+        if self.lines.len() < count {
+            self.lines.resize_with(count, Vec::new);
+        }
+        self.row -= count;
+        let mut blocks = vec![];
+        for line in self.lines.drain(..count) {
+            push_cells(&mut blocks, line);
+            push_newline(&mut blocks);
+        }
+        blocks
+    }
+
+    /// Returns the last lines of the grid, up to the given height, as blocks of text alongside the
+    /// state each block of text should be formatted with. Lines are separated by unformatted
+    /// newlines.
+    pub(crate) fn screen(&self, height: usize) -> Vec<(Style, String)> {
+        let start = self.lines.len().saturating_sub(height);
+        let mut blocks = vec![];
+        for (i, line) in self.lines[start..].iter().enumerate() {
+            if i > 0 {
+                push_newline(&mut blocks);
+            }
+            push_cells(&mut blocks, line.iter().cloned());
+        }
+        blocks
+    }
+
+    /// Returns the contents of the grid as blocks of text alongside the state each block of text
+    /// should be formatted with. Lines are separated by unformatted newlines.
+    pub(crate) fn into_blocks(self) -> Vec<(Style, String)> {
+        let mut blocks = vec![];
+        for (i, line) in self.lines.into_iter().enumerate() {
+            if i > 0 {
+                push_newline(&mut blocks);
+            }
+            push_cells(&mut blocks, line);
+        }
+        blocks
+    }
+}
+
+/// Append cells to a list of blocks, merging cells with the same state.
+fn push_cells(blocks: &mut Vec<(Style, String)>, cells: impl IntoIterator<Item = Cell>) {
+    for cell in cells {
+        match blocks.last_mut() {
+            Some((state, text)) if *state == cell.state => text.push(cell.character),
+            _ => blocks.push((cell.state, cell.character.to_string())),
+        }
+    }
+}
+
+/// Append an unformatted newline to a list of blocks.
+fn push_newline(blocks: &mut Vec<(Style, String)>) {
+    let newline = Cell {
+        character: '\n',
+        ..Default::default()
+    };
+    push_cells(blocks, [newline]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn replay(input: &str, width: Option<usize>) -> String {
+        replay_with_height(input, width, None)
+    }
+
+    fn replay_with_height(input: &str, width: Option<usize>, height: Option<usize>) -> String {
+        let mut terminal = Terminal::new(width, height);
+        for sequence in parse(input) {
+            terminal.feed(&sequence);
+        }
+        terminal
+            .into_blocks()
+            .into_iter()
+            .map(|(_, text)| text)
+            .collect()
+    }
+
+    #[test]
+    fn replaying_carriage_returns() {
+        let input = "Building [=>   ] 1/3\rBuilding [==>  ] 2/3\rBuilding [====>] 3/3\n";
+        assert_eq!(replay(input, None), "Building [====>] 3/3\n");
+        assert_eq!(replay("abcdef\rxy\n", None), "xycdef\n");
+    }
+
+    #[test]
+    fn replaying_control_characters() {
+        assert_eq!(replay("abc\x08\x08X", None), "aXc");
+        assert_eq!(replay("a\tb\tc", None), "a       b       c");
+        assert_eq!(replay("\x08\x08a", None), "a");
+    }
+
+    #[test]
+    fn replaying_cursor_movement() {
+        assert_eq!(replay("one\ntwo\n\x1b[2Athree\n", None), "three\ntwo\n");
+        assert_eq!(replay("a\x1b[3Cb", None), "a   b");
+        assert_eq!(replay("abcd\x1b[3Dx", None), "axcd");
+        assert_eq!(replay("abcd\x1b[2Gx", None), "axcd");
+        assert_eq!(replay("a\x1b[2Bb", None), "a\n\n b");
+        assert_eq!(replay("abc\ndef\x1b[1;2Hx", None), "axc\ndef");
+    }
+
+    #[test]
+    fn limiting_cursor_movement() {
+        let input = "\x1b[65535C".repeat(1600) + "x";
+        let output = replay(&input, None);
+        assert_eq!(output.len(), MAX_COLUMNS);
+        assert!(output.ends_with(" x"));
+
+        let input = "\x1b[65535B".repeat(1600) + "\x1b[65535;65535Hx";
+        let output = replay(&input, None);
+        assert_eq!(output.matches('\n').count(), MAX_ROWS - 1);
+        assert_eq!(output.len(), MAX_ROWS - 1 + MAX_COLUMNS);
+
+        // Text can still move the cursor further than movement sequences can
+        let input = "a".repeat(MAX_COLUMNS + 2) + "\x08\x1b[Cx";
+        assert!(replay(&input, None).ends_with("ax"));
+    }
+
+    #[test]
+    fn replaying_erase_sequences() {
+        assert_eq!(replay("abcdef\x1b[3D\x1b[K", None), "abc");
+        assert_eq!(replay("abcdef\x1b[3D\x1b[1K", None), "    ef");
+        assert_eq!(replay("abcdef\x1b[2Kx", None), "      x");
+        assert_eq!(replay("abc\ndef\nghi\x1b[1A\r\x1b[J", None), "abc\n");
+        assert_eq!(replay("abc\ndef\x1b[2J\x1b[Hx", None), "x\n");
+    }
+
+    #[test]
+    fn erasing_the_screen() {
+        let input = "one\ntwo\nthree\nfour\x1b[H\x1b[2Jfive";
+        assert_eq!(replay_with_height(input, None, Some(2)), "one\ntwo\nfive\n");
+        let input = "one\ntwo\nthree\nfour\x1b[H\x1b[3Jfive";
+        assert_eq!(replay_with_height(input, None, Some(2)), "\n\nfive\n");
+
+        // Without a height, output before the last screen of the default height is kept
+        let input = "line\n".repeat(DEFAULT_ROWS + 1) + "\x1b[H\x1b[2Jx";
+        let output = replay(&input, None);
+        assert!(output.starts_with("line\nline\nx\n"));
+        assert_eq!(output.matches("line").count(), 2);
+    }
+
+    #[test]
+    fn replaying_with_width() {
+        assert_eq!(replay("abcdef", Some(4)), "abcd\nef");
+        assert_eq!(replay("abcd\rx", Some(4)), "xbcd");
+        assert_eq!(replay("ab\x1b[10Cx", Some(4)), "ab x");
+    }
+
+    #[test]
+    fn taking_final_lines() {
+        let mut terminal = Terminal::new(None, None);
+        let input = "line\n".repeat(SCROLLBACK + 2);
+        for sequence in parse(input.as_str()) {
+            terminal.feed(&sequence);
+        }
+        let taken: String = terminal
+            .take_final_lines()
+            .into_iter()
+            .map(|(_, text)| text)
+            .collect();
+        assert_eq!(taken, "line\nline\n");
+        assert!(terminal.take_final_lines().is_empty());
+        assert_eq!(terminal.lines.len(), SCROLLBACK + 1);
+    }
+
+    #[test]
+    fn taking_screen() {
+        let mut terminal = Terminal::new(None, None);
+        for sequence in parse("one\ntwo\nthree") {
+            terminal.feed(&sequence);
+        }
+        let screen: String = terminal
+            .screen(2)
+            .into_iter()
+            .map(|(_, text)| text)
+            .collect();
+        assert_eq!(screen, "two\nthree");
+        assert_eq!(terminal.lines.len(), 3);
+    }
+
+    #[test]
+    fn replaying_styles() {
+        let mut terminal = Terminal::new(None, None);
+        for sequence in parse("\x1b[31mred\x1b[0m\r\x1b[32mg") {
+            terminal.feed(&sequence);
+        }
+        let blocks = terminal.into_blocks();
+        let green = Style::default().clone_from_scan(&[32]);
+        let red = Style::default().clone_from_scan(&[31]);
+        assert_eq!(
+            blocks,
+            vec![(green, "g".to_string()), (red, "ed".to_string())]
+        );
+    }
+}
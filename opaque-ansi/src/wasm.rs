@@ -0,0 +1,19 @@
+//! JavaScript bindings, for rendering terminal output in the browser. The bindings can be built
+//! for `wasm32-unknown-unknown` and then passed to `wasm-bindgen`:
+//!
+//! ```sh
+//! cargo rustc -p opaque-ansi --lib --release --features wasm --target wasm32-unknown-unknown --crate-type cdylib
+//! wasm-bindgen --target web --out-dir pkg target/wasm32-unknown-unknown/release/opaque_ansi.wasm
+//! ```
+
+use alloc::string::String;
+
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// Render an ANSI terminal output to HTML, using the default [`crate::RenderOptions`]. Exported to
+/// JavaScript as `rewriteAnsiToHtml`.
+#[wasm_bindgen(js_name = rewriteAnsiToHtml)]
+#[must_use]
+pub fn rewrite_ansi_to_html(input: &str) -> String {
+    crate::rewrite_ansi_to_html(input)
+}
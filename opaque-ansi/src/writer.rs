@@ -0,0 +1,649 @@
+//! A streaming renderer, converting ANSI terminal output to HTML as it is written.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use core::convert::Infallible;
+#[cfg(feature = "std")]
+use core::{mem, str};
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+
+use crate::parser::split_sequence;
+#[cfg(feature = "std")]
+use crate::parser::Sequence;
+use crate::{BlockParser, RenderOptions, Style, StyleMode};
+
+/// The longest incomplete escape sequence kept while waiting for more input. Anything longer is
+/// assumed to be malformed and is discarded, so that memory usage stays bounded.
+const MAX_PENDING_LENGTH: usize = 4096;
+
+/// The tags opening the rendered output.
+const OPENING_TAGS: &str = "<pre class=\"ansi_output\"><code>";
+
+/// The tags closing the rendered output.
+const CLOSING_TAGS: &str = "</code></pre>";
+
+/// Render a block of text to HTML, using the state to generate formatting.
+fn render_block(state: &Style, text: &str, options: &RenderOptions) -> String {
+    let state = options.color_adjustment.apply(state);
+    let state = state.as_ref();
+    if options.compact {
+        return render_compact_block(state, text, options);
+    }
+    let (mut opening_tags, mut closing_tags) = match &options.style_mode {
+        StyleMode::Inline => state.build_tags(),
+        StyleMode::Class { prefix } => state.build_class_tags(prefix),
+    };
+    let hyperlink = state
+        .hyperlink
+        .as_deref()
+        .and_then(|url| options.file_links.resolve(url));
+    if let Some(url) = hyperlink {
+        let url = html_escape::encode_double_quoted_attribute(url.as_str());
+        opening_tags = format!("<a href=\"{url}\">{opening_tags}");
+        closing_tags.push_str("</a>");
+    }
+    let text = html_escape::encode_text(text);
+    format!("{opening_tags}{text}{closing_tags}")
+}
+
+/// Render a block of text to HTML as a single element, combining every style or class into one
+/// attribute. Hyperlinks carry the formatting themselves rather than wrapping a `<span>`.
+fn render_compact_block(state: &Style, text: &str, options: &RenderOptions) -> String {
+    let attribute = match &options.style_mode {
+        StyleMode::Inline => ("style", state.build_styles().join("; ")),
+        StyleMode::Class { prefix } => ("class", state.build_classes(prefix).join(" ")),
+    };
+    let attribute = match attribute {
+        (_, value) if value.is_empty() => String::new(),
+        (name, value) => format!(" {name}=\"{value}\""),
+    };
+    let hyperlink = state
+        .hyperlink
+        .as_deref()
+        .and_then(|url| options.file_links.resolve(url));
+    let text = html_escape::encode_text(text);
+    match hyperlink {
+        Some(url) => {
+            let url = html_escape::encode_double_quoted_attribute(url.as_str());
+            format!("<a href=\"{url}\"{attribute}>{text}</a>")
+        }
+        None if attribute.is_empty() => text.into_owned(),
+        None => format!("<span{attribute}>{text}</span>"),
+    }
+}
+
+/// Renders blocks of text to an HTML buffer. When [`RenderOptions::compact`] is set, adjacent
+/// blocks with the same formatting are merged before being rendered, and whitespace that only has
+/// foreground formatting is merged into its neighbours. Lines outside of [`RenderOptions::lines`]
+/// are skipped, and lines are wrapped in `<span class="ansi_line">` when line numbers or
+/// highlighted lines are used. Folded lines are kept until the end of the fold, since the number
+/// of lines is shown before them.
+struct BlockWriter {
+    html: String,
+    options: RenderOptions,
+    merged: Option<(Style, String)>,
+    line: usize,
+    line_start: bool,
+    line_open: bool,
+    fold: Option<(usize, String)>,
+}
+
+impl BlockWriter {
+    fn new(options: RenderOptions) -> Self {
+        BlockWriter {
+            html: String::new(),
+            options,
+            merged: None,
+            line: 1,
+            line_start: true,
+            line_open: false,
+            fold: None,
+        }
+    }
+
+    /// Returns the buffer rendered HTML is written to, which is the fold while in a fold.
+    fn output(&mut self) -> &mut String {
+        match &mut self.fold {
+            Some((_, folded)) => folded,
+            None => &mut self.html,
+        }
+    }
+
+    /// Render and write a block of text, skipping any lines that aren't rendered.
+    fn push(&mut self, state: &Style, text: &str) {
+        let split_lines = self.options.line_numbers
+            || !self.options.highlight_lines.is_empty()
+            || !self.options.fold_lines.is_empty()
+            || self.options.lines.is_some();
+        if !split_lines {
+            return self.push_text(state, text);
+        }
+
+        for piece in text.split_inclusive('\n') {
+            let in_range = self
+                .options
+                .lines
+                .as_ref()
+                .is_none_or(|lines| lines.contains(&self.line));
+            if in_range {
+                if self.line_start {
+                    self.start_line();
+                }
+                self.push_text(state, piece);
+            }
+            if piece.ends_with('\n') {
+                self.close_line();
+                self.line += 1;
+                self.line_start = true;
+            }
+        }
+    }
+
+    /// Start or end a fold before the current line if needed, and write its opening tag.
+    fn start_line(&mut self) {
+        self.line_start = false;
+        let folded = self
+            .options
+            .fold_lines
+            .iter()
+            .any(|lines| lines.contains(&self.line));
+        if folded != self.fold.is_some() {
+            self.write_merged();
+            if folded {
+                self.fold = Some((0, String::new()));
+            } else {
+                self.write_fold();
+            }
+        }
+        if let Some((count, _)) = &mut self.fold {
+            *count += 1;
+        }
+
+        if self.options.line_numbers || !self.options.highlight_lines.is_empty() {
+            self.open_line();
+        }
+    }
+
+    /// Write the current fold, if any, as a collapsed `<details>` element.
+    fn write_fold(&mut self) {
+        if let Some((count, folded)) = self.fold.take() {
+            let lines = if count == 1 { "line" } else { "lines" };
+            self.html.push_str(&format!(
+                "<details class=\"ansi_fold\"><summary>… {count} {lines} hidden</summary>"
+            ));
+            self.html.push_str(&folded);
+            self.html.push_str("</details>");
+        }
+    }
+
+    /// Write the opening tag of the current line.
+    fn open_line(&mut self) {
+        self.write_merged();
+        let mut classes = "ansi_line".to_string();
+        let highlighted = self
+            .options
+            .highlight_lines
+            .iter()
+            .any(|lines| lines.contains(&self.line));
+        if highlighted {
+            classes.push_str(" ansi_line_highlight");
+        }
+        let line_number = if self.options.line_numbers {
+            format!(" data-line=\"{}\"", self.line)
+        } else {
+            String::new()
+        };
+        self.output()
+            .push_str(&format!("<span class=\"{classes}\"{line_number}>"));
+        self.line_open = true;
+    }
+
+    /// Write the closing tag of the current line, if it was opened.
+    fn close_line(&mut self) {
+        if self.line_open {
+            self.write_merged();
+            self.output().push_str("</span>");
+            self.line_open = false;
+        }
+    }
+
+    /// Write anything that remains after the last block of text.
+    fn finish(&mut self) {
+        self.write_merged();
+        self.close_line();
+        self.write_fold();
+    }
+
+    /// Render and write a block of text, or merge it with the previous block.
+    fn push_text(&mut self, state: &Style, text: &str) {
+        if !self.options.compact {
+            let block = render_block(state, text, &self.options);
+            return self.output().push_str(&block);
+        }
+
+        let state = if text.chars().all(char::is_whitespace) && state.is_foreground_only() {
+            // The formatting isn't visible, so use whichever state merges with the previous block
+            match &self.merged {
+                Some((merged, _)) if merged.is_foreground_only() => merged.clone(),
+                _ => Style::default(),
+            }
+        } else {
+            state.clone()
+        };
+
+        match &mut self.merged {
+            Some((merged, merged_text)) if *merged == state => merged_text.push_str(text),
+            _ => {
+                self.write_merged();
+                self.merged = Some((state, text.to_string()));
+            }
+        }
+
+        // Bound the memory used by long runs of text with the same formatting
+        if self
+            .merged
+            .as_ref()
+            .is_some_and(|(_, text)| text.len() > MAX_PENDING_LENGTH)
+        {
+            self.write_merged();
+        }
+    }
+
+    /// Render and write the merged block of text, if any.
+    fn write_merged(&mut self) {
+        if let Some((state, text)) = self.merged.take() {
+            let block = render_block(&state, &text, &self.options);
+            self.output().push_str(&block);
+        }
+    }
+
+    /// Returns the HTML rendered so far, outside of any fold, leaving the buffer empty.
+    #[cfg(feature = "std")]
+    fn take_html(&mut self) -> String {
+        mem::take(&mut self.html)
+    }
+}
+
+/// Render blocks of text to HTML, without the tags surrounding the output.
+pub(crate) fn render_blocks<'a>(
+    blocks: impl IntoIterator<Item = (&'a Style, &'a str)>,
+    options: &RenderOptions,
+) -> String {
+    let mut output = BlockWriter::new(options.clone());
+    for (state, text) in blocks {
+        output.push(state, text);
+    }
+    output.finish();
+    output.html
+}
+
+/// Render a complete ANSI terminal output to HTML, including the tags surrounding the output.
+/// Incomplete escape sequences at the end of the input are discarded.
+pub(crate) fn render_html(input: &str, options: &RenderOptions) -> String {
+    let mut parser = BlockParser::new(options);
+    let mut output = BlockWriter::new(options.clone());
+    let mut push = |state: &Style, text: &str| {
+        output.push(state, text);
+        Ok::<(), Infallible>(())
+    };
+
+    let mut input = input;
+    while let Some((sequence, rest)) = split_sequence(input) {
+        let Ok(()) = parser.feed(&sequence, &mut push);
+        input = rest;
+    }
+    let Ok(()) = parser.finish(&mut push);
+    output.finish();
+
+    format!("{OPENING_TAGS}{}{CLOSING_TAGS}", output.html)
+}
+
+/// Render ANSI terminal output to HTML as it is written, streaming the HTML to an inner writer.
+///
+/// Input may be written in chunks of any size, including chunks that split escape sequences or
+/// UTF-8 characters; incomplete sequences are kept until the rest of the sequence is written.
+/// Invalid UTF-8 is replaced with `U+FFFD`. [`AnsiHtmlWriter::finish`] must be called once all
+/// input has been written, to close the HTML tags opened by the writer.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::io::Write;
+/// use opaque_ansi::{AnsiHtmlWriter, RenderOptions};
+///
+/// let mut writer = AnsiHtmlWriter::new(vec![], RenderOptions::default());
+/// writer.write_all(b"\x1b[1").unwrap();
+/// writer.write_all(b"mbold\x1b[0m").unwrap();
+/// let html = writer.finish().unwrap();
+/// assert_eq!(
+///     String::from_utf8(html).unwrap(),
+///     "<pre class=\"ansi_output\"><code><strong>bold</strong></code></pre>",
+/// );
+/// ```
+#[cfg(feature = "std")]
+pub struct AnsiHtmlWriter<W: Write> {
+    inner: W,
+    output: BlockWriter,
+    parser: BlockParser,
+    pending: Vec<u8>,
+    started: bool,
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> AnsiHtmlWriter<W> {
+    /// Create a writer rendering HTML to the inner writer using the given options.
+    pub fn new(inner: W, options: RenderOptions) -> Self {
+        AnsiHtmlWriter {
+            inner,
+            parser: BlockParser::new(&options),
+            output: BlockWriter::new(options),
+            pending: vec![],
+            started: false,
+        }
+    }
+
+    /// Write the opening tags of the output, if they haven't been written already.
+    fn start(&mut self) -> io::Result<()> {
+        if !self.started {
+            self.inner.write_all(OPENING_TAGS.as_bytes())?;
+            self.started = true;
+        }
+        Ok(())
+    }
+
+    /// Write the HTML rendered so far to the inner writer.
+    fn write_html(&mut self) -> io::Result<()> {
+        self.inner.write_all(self.output.take_html().as_bytes())
+    }
+
+    /// Render a single sequence.
+    fn feed(&mut self, sequence: &Sequence) {
+        let output = &mut self.output;
+        let Ok(()) = self.parser.feed(sequence, &mut |state, text| {
+            output.push(state, text);
+            Ok::<(), Infallible>(())
+        });
+    }
+
+    /// Render every complete sequence from the valid UTF-8 input, returning the length of any
+    /// incomplete sequence at the end of the input.
+    fn feed_str(&mut self, mut input: &str) -> usize {
+        while let Some((sequence, rest)) = split_sequence(input) {
+            self.feed(&sequence);
+            input = rest;
+        }
+        input.len()
+    }
+
+    /// Render every complete sequence from the pending input, keeping any incomplete sequence or
+    /// UTF-8 character as pending input.
+    fn process(&mut self) {
+        let pending = mem::take(&mut self.pending);
+        let mut input = &pending[..];
+
+        loop {
+            let (valid, invalid_length) = match str::from_utf8(input) {
+                Ok(valid) => (valid, None),
+                Err(e) => {
+                    let valid = str::from_utf8(&input[..e.valid_up_to()])
+                        .expect("input is valid up to the error");
+                    (valid, e.error_len())
+                }
+            };
+            let incomplete = self.feed_str(valid);
+
+            match invalid_length {
+                // Invalid bytes terminate any incomplete sequence
+                Some(length) => {
+                    self.feed(&Sequence::Text("\u{fffd}"));
+                    input = &input[valid.len() + length..];
+                }
+                None => {
+                    input = &input[valid.len() - incomplete..];
+                    break;
+                }
+            }
+        }
+
+        if input.len() <= MAX_PENDING_LENGTH {
+            self.pending = input.to_vec();
+        }
+    }
+
+    /// Render any remaining input and write the closing tags of the output, returning the inner
+    /// writer. Incomplete escape sequences at the end of the input are discarded.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error encountered while writing to the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.start()?;
+
+        let pending = mem::take(&mut self.pending);
+        if let Some(utf8_error) = str::from_utf8(&pending).err() {
+            // Only an incomplete UTF-8 character can remain, which can never be completed
+            let valid = str::from_utf8(&pending[..utf8_error.valid_up_to()])
+                .expect("input is valid up to the error");
+            self.feed_str(valid);
+            self.feed(&Sequence::Text("\u{fffd}"));
+        }
+
+        let output = &mut self.output;
+        let Ok(()) = self.parser.finish(&mut |state, text| {
+            output.push(state, text);
+            Ok::<(), Infallible>(())
+        });
+        self.output.finish();
+
+        let mut inner = self.inner;
+        inner.write_all(self.output.html.as_bytes())?;
+        inner.write_all(CLOSING_TAGS.as_bytes())?;
+        inner.flush()?;
+        Ok(inner)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> Write for AnsiHtmlWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.start()?;
+        self.pending.extend_from_slice(buf);
+        self.process();
+        self.write_html()?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.write_merged();
+        self.write_html()?;
+        self.inner.flush()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    fn render_chunks(chunks: &[&[u8]], options: RenderOptions) -> String {
+        let mut writer = AnsiHtmlWriter::new(vec![], options);
+        for chunk in chunks {
+            writer.write_all(chunk).unwrap();
+        }
+        String::from_utf8(writer.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn writing_split_sequences() {
+        // Text is a single character between sequences so that every split is within a sequence
+        let input = "\x1b[1;31mr\x1b[0m \x1b]8;;http://x\x1b\\é\x1b]8;;\x1b\\".as_bytes();
+        let expected = crate::rewrite_ansi_to_html(std::str::from_utf8(input).unwrap());
+        for i in 0..input.len() {
+            let (first, second) = input.split_at(i);
+            assert_eq!(
+                render_chunks(&[first, second], RenderOptions::default()),
+                expected,
+                "split at {i}"
+            );
+        }
+        let bytes: Vec<&[u8]> = input.chunks(1).collect();
+        assert_eq!(render_chunks(&bytes, RenderOptions::default()), expected);
+    }
+
+    #[test]
+    fn writing_compact_markup() {
+        let options = RenderOptions {
+            compact: true,
+            ..Default::default()
+        };
+        let input =
+            b"\x1b[1;31merror\x1b[0m\x1b[1;31m: \x1b[0m\x1b[32m  \x1b[0m\x1b[4m \x1b[0mdone";
+        let expected = "<pre class=\"ansi_output\"><code>\
+                        <span style=\"font-weight: bold; color: var(--color-red)\">error:   </span>\
+                        <span style=\"text-decoration-line: underline\"> </span>done\
+                        </code></pre>";
+        assert_eq!(render_chunks(&[input], options.clone()), expected);
+
+        // Merging doesn't depend on how the input is split
+        for i in 0..input.len() {
+            let (first, second) = input.split_at(i);
+            assert_eq!(
+                render_chunks(&[first, second], options.clone()),
+                expected,
+                "split at {i}"
+            );
+        }
+
+        let options = RenderOptions {
+            style_mode: StyleMode::class(),
+            compact: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            render_chunks(
+                &[b"\x1b]8;;http://x\x07\x1b[2ma\x1b]8;;\x07\x1b[0m b"],
+                options
+            ),
+            "<pre class=\"ansi_output\"><code>\
+             <a href=\"http://x\" class=\"ansi-dim\">a</a> b\
+             </code></pre>"
+        );
+    }
+
+    #[test]
+    fn writing_line_ranges() {
+        let input = b"\x1b[31ma\nb\x1b[0m\nc\nd\n";
+        let options = RenderOptions {
+            lines: Some(2..=3),
+            ..Default::default()
+        };
+        assert_eq!(
+            render_chunks(&[input], options.clone()),
+            "<pre class=\"ansi_output\"><code>\
+             <span style=\"color: var(--color-red)\">b</span>\nc\n\
+             </code></pre>"
+        );
+
+        let options = RenderOptions {
+            line_numbers: true,
+            highlight_lines: vec![3..=3],
+            compact: true,
+            ..options
+        };
+        assert_eq!(
+            render_chunks(&[input], options),
+            "<pre class=\"ansi_output\"><code>\
+             <span class=\"ansi_line\" data-line=\"2\">\
+             <span style=\"color: var(--color-red)\">b\n</span></span>\
+             <span class=\"ansi_line ansi_line_highlight\" data-line=\"3\">c\n</span>\
+             </code></pre>"
+        );
+
+        // A line without a trailing newline is still closed
+        let options = RenderOptions {
+            highlight_lines: vec![1..=1],
+            ..Default::default()
+        };
+        assert_eq!(
+            render_chunks(&[b"a\nb"], options),
+            "<pre class=\"ansi_output\"><code>\
+             <span class=\"ansi_line ansi_line_highlight\">a\n</span>\
+             <span class=\"ansi_line\">b</span>\
+             </code></pre>"
+        );
+    }
+
+    #[test]
+    fn writing_folds() {
+        let input = b"\x1b[32ma\nb\nc\x1b[0m\nd\ne\n";
+        let options = RenderOptions {
+            fold_lines: vec![1..=2, 3..=3, 5..=9],
+            ..Default::default()
+        };
+        assert_eq!(
+            render_chunks(&[input], options),
+            "<pre class=\"ansi_output\"><code>\
+             <details class=\"ansi_fold\"><summary>… 3 lines hidden</summary>\
+             <span style=\"color: var(--color-green)\">a\n</span>\
+             <span style=\"color: var(--color-green)\">b\n</span>\
+             <span style=\"color: var(--color-green)\">c</span>\n\
+             </details>d\n\
+             <details class=\"ansi_fold\"><summary>… 1 line hidden</summary>e\n</details>\
+             </code></pre>"
+        );
+
+        let options = RenderOptions {
+            fold_lines: vec![2..=2],
+            line_numbers: true,
+            compact: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            render_chunks(&[b"a\nb\nc"], options),
+            "<pre class=\"ansi_output\"><code>\
+             <span class=\"ansi_line\" data-line=\"1\">a\n</span>\
+             <details class=\"ansi_fold\"><summary>… 1 line hidden</summary>\
+             <span class=\"ansi_line\" data-line=\"2\">b\n</span></details>\
+             <span class=\"ansi_line\" data-line=\"3\">c</span>\
+             </code></pre>"
+        );
+    }
+
+    #[test]
+    fn writing_invalid_utf8() {
+        assert_eq!(
+            render_chunks(&[b"a\xffb", b"\xc3"], RenderOptions::default()),
+            "<pre class=\"ansi_output\"><code>a\u{fffd}b\u{fffd}</code></pre>"
+        );
+    }
+
+    #[test]
+    fn writing_incomplete_sequences() {
+        assert_eq!(
+            render_chunks(&[b"a\x1b[1"], RenderOptions::default()),
+            "<pre class=\"ansi_output\"><code>a</code></pre>"
+        );
+        assert_eq!(
+            render_chunks(&[b""], RenderOptions::default()),
+            "<pre class=\"ansi_output\"><code></code></pre>"
+        );
+
+        // An unterminated sequence is eventually discarded
+        let mut writer = AnsiHtmlWriter::new(vec![], RenderOptions::default());
+        writer.write_all(b"\x1b]0;").unwrap();
+        writer.write_all(&[b'a'; MAX_PENDING_LENGTH]).unwrap();
+        assert!(writer.pending.is_empty());
+    }
+
+    #[test]
+    fn writing_with_terminal() {
+        let options = RenderOptions {
+            emulate_terminal: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            render_chunks(&[b"1/2\r", b"2/2\n"], options),
+            "<pre class=\"ansi_output\"><code>2/2\n</code></pre>"
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use std::ops::RangeInclusive;
+
+/// Options given after the language in the info string of a code fence, such as
+/// `rust {3,5-7} linenos start=40`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct FenceInfo {
+    /// The language the code block is highlighted as.
+    pub(crate) language: Option<String>,
+    /// Lines to highlight, given between braces. Lines are numbered from 1, regardless of
+    /// [`FenceInfo::start`].
+    pub(crate) highlight_lines: Vec<RangeInclusive<usize>>,
+    /// Show the number of every line, given by `linenos`.
+    pub(crate) line_numbers: bool,
+    /// The number of the first line, given by `start=40`.
+    pub(crate) start: Option<usize>,
+    /// A caption shown above the code block, such as a file name, given by `title="src/main.rs"`.
+    pub(crate) title: Option<String>,
+}
+
+/// Split an info string into words at whitespace, except for whitespace between double quotes or
+/// within a group of line numbers between braces. The quotes are removed.
+fn words(info: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = None;
+    let mut quoted = false;
+    let mut braced = false;
+    for character in info.chars() {
+        match character {
+            '"' if !braced => {
+                quoted = !quoted;
+                word.get_or_insert_with(String::new);
+            }
+            character if character.is_whitespace() && !quoted && !braced => {
+                words.extend(word.take());
+            }
+            character => {
+                if !quoted {
+                    braced = match character {
+                        '{' => true,
+                        '}' => false,
+                        _ => braced,
+                    };
+                }
+                word.get_or_insert_with(String::new).push(character);
+            }
+        }
+    }
+    words.extend(word);
+    words
+}
+
+/// Parse a line number or a range of line numbers, such as `5-7`. A range without an end, such as
+/// `120-`, continues until the last line.
+fn parse_line_range(input: &str) -> Option<RangeInclusive<usize>> {
+    Some(match input.split_once('-') {
+        Some((start, "")) => start.trim().parse().ok()?..=usize::MAX,
+        Some((start, end)) => start.trim().parse().ok()?..=end.trim().parse().ok()?,
+        None => {
+            let line = input.trim().parse().ok()?;
+            line..=line
+        }
+    })
+}
+
+impl FenceInfo {
+    /// Parse the info string of a code fence. The first word that isn't an option is the language.
+    /// Unknown options and invalid line numbers are ignored, so that a typo in a code fence doesn't
+    /// stop the rest of the page from rendering.
+    pub(crate) fn parse(info: &str) -> FenceInfo {
+        let mut fence = FenceInfo::default();
+        for word in words(info) {
+            if let Some(ranges) = word.strip_prefix('{').and_then(|w| w.strip_suffix('}')) {
+                fence
+                    .highlight_lines
+                    .extend(ranges.split(',').filter_map(parse_line_range));
+            } else if let Some(title) = word.strip_prefix("title=") {
+                fence.title = (!title.is_empty()).then(|| title.to_string());
+            } else if word == "linenos" {
+                fence.line_numbers = true;
+            } else if let Some(start) = word.strip_prefix("start=") {
+                fence.start = start.parse().ok();
+            } else if fence.language.is_none() && !word.contains('=') && !word.starts_with('{') {
+                fence.language = Some(word);
+            }
+        }
+        fence
+    }
+
+    /// Whether the info string gave anything other than a language.
+    pub(crate) fn has_options(&self) -> bool {
+        self.title.is_some() || self.wraps_lines()
+    }
+
+    /// The language lines of a diff are highlighted as, for a language such as `diff-rust`.
+    pub(crate) fn diff_language(&self) -> Option<&str> {
+        self.language.as_deref()?.strip_prefix("diff-")
+    }
+
+    /// Whether every line is wrapped in its own element, to number, highlight or link to it.
+    pub(crate) fn wraps_lines(&self) -> bool {
+        self.line_numbers
+            || !self.highlight_lines.is_empty()
+            || self.start.is_some()
+            || self.diff_language().is_some()
+    }
+
+    /// The number shown for the first line.
+    pub(crate) fn first_line(&self) -> usize {
+        self.start.unwrap_or(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing_fences() {
+        let fence = FenceInfo::parse("rust {3,5-7} linenos start=40");
+        assert_eq!(fence.language.as_deref(), Some("rust"));
+        assert_eq!(fence.highlight_lines, vec![3..=3, 5..=7]);
+        assert!(fence.line_numbers);
+        assert_eq!(fence.start, Some(40));
+        assert_eq!(fence.first_line(), 40);
+
+        let fence = FenceInfo::parse("{3, 5-7, 120-} rust");
+        assert_eq!(fence.language.as_deref(), Some("rust"));
+        assert_eq!(fence.highlight_lines, vec![3..=3, 5..=7, 120..=usize::MAX]);
+
+        let fence = FenceInfo::parse("diff-rust title=\"src/my main.rs\"");
+        assert_eq!(fence.title.as_deref(), Some("src/my main.rs"));
+        assert_eq!(fence.diff_language(), Some("rust"));
+        assert!(fence.wraps_lines());
+
+        let fence = FenceInfo::parse("rust");
+        assert!(!fence.has_options());
+        assert_eq!(fence.first_line(), 1);
+    }
+
+    #[test]
+    fn parsing_invalid_fences() {
+        let fence = FenceInfo::parse("{x, 2, 3-y} start=z title=\"\" colour=red");
+        assert_eq!(fence.language, None);
+        assert_eq!(fence.highlight_lines, vec![2..=2]);
+        assert_eq!(fence.start, None);
+        assert_eq!(fence.title, None);
+
+        assert_eq!(FenceInfo::parse(""), FenceInfo::default());
+        let fence = FenceInfo::parse("{3, 5 rust");
+        assert_eq!(fence.language, None);
+        assert!(fence.highlight_lines.is_empty());
+    }
+}
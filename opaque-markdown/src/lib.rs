@@ -1,5 +1,7 @@
 use comrak::{
-    format_html_with_plugins, nodes::AstNode, parse_document, Arena, ComrakOptions, ComrakPlugins,
+    format_html_with_plugins,
+    nodes::{AstNode, NodeValue},
+    parse_document, Arena, ComrakOptions, ComrakPlugins,
 };
 use eyre::{Result, WrapErr};
 
@@ -8,26 +10,95 @@ use tracing::debug;
 
 use std::path::Path;
 
+mod fence;
+mod syntaxes;
 mod syntect_adapter;
+mod themes;
 
-/// Create opinionated defaults for Comrak.
-fn create_options() -> ComrakOptions {
-    let mut comrak_options = ComrakOptions::default();
-    comrak_options.extension.strikethrough = true;
-    comrak_options.extension.table = true;
-    comrak_options.extension.autolink = true;
-    comrak_options.extension.tasklist = true;
-    comrak_options.extension.header_ids = Some("md-header-".to_string());
-    comrak_options.extension.description_lists = true;
-    comrak_options.extension.front_matter_delimiter = Some("---".to_string());
-    comrak_options.render.unsafe_ = true;
-    comrak_options.render.unsafe_ = true;
-
-    comrak_options
+pub use syntaxes::{SyntaxOptions, SyntaxRegistry};
+pub use themes::{syntax_stylesheet, DEFAULT_DARK_THEME, DEFAULT_LIGHT_THEME};
+
+/// The classes given to highlighted code, shared by the adapter and the generated stylesheet.
+const CLASS_STYLE: syntect::html::ClassStyle =
+    syntect::html::ClassStyle::SpacedPrefixed { prefix: "syntect-" };
+
+/// Options for rendering Markdown to HTML, choosing which Comrak extensions are enabled. The
+/// defaults are the opinionated options used by [`render_to_html`].
+///
+/// # Examples
+///
+/// ```rust
+/// let options = opaque_markdown::MarkdownOptions {
+///     footnotes: true,
+///     smart_punctuation: true,
+///     ..Default::default()
+/// };
+/// let html = opaque_markdown::render_to_html_with("\"Hello\" -- world", &options).unwrap();
+/// assert_eq!(html, "<p>“Hello” – world</p>\n");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarkdownOptions {
+    /// Render text between `~~` as struck through.
+    pub strikethrough: bool,
+    /// Render GitHub-style tables.
+    pub tables: bool,
+    /// Turn bare URLs and email addresses into links.
+    pub autolinks: bool,
+    /// Render list items starting with `[ ]` or `[x]` as checkboxes.
+    pub task_lists: bool,
+    /// Render description lists, where each term is followed by a line starting with `:`.
+    pub description_lists: bool,
+    /// Render footnote references such as `[^1]` and their definitions.
+    pub footnotes: bool,
+    /// Render text after `^` as superscript.
+    pub superscript: bool,
+    /// Replace straight quotes, `--`, `---`, and `...` with their typographic equivalents.
+    pub smart_punctuation: bool,
+    /// Render every newline within a paragraph as a line break.
+    pub hard_breaks: bool,
+    /// The prefix of the IDs given to headers, or `None` to not give headers IDs.
+    pub header_id_prefix: Option<String>,
+    /// Render raw HTML and links with dangerous schemes as-is, rather than omitting them.
+    pub unsafe_html: bool,
+}
+
+impl Default for MarkdownOptions {
+    fn default() -> Self {
+        MarkdownOptions {
+            strikethrough: true,
+            tables: true,
+            autolinks: true,
+            task_lists: true,
+            description_lists: true,
+            footnotes: false,
+            superscript: false,
+            smart_punctuation: false,
+            hard_breaks: false,
+            header_id_prefix: Some("md-header-".to_string()),
+            unsafe_html: true,
+        }
+    }
 }
 
-lazy_static::lazy_static! {
-    static ref COMRAK_OPTIONS: ComrakOptions = create_options();
+impl MarkdownOptions {
+    /// Create the Comrak options for the chosen extensions. Front matter is always skipped.
+    fn comrak_options(&self) -> ComrakOptions {
+        let mut comrak_options = ComrakOptions::default();
+        comrak_options.extension.strikethrough = self.strikethrough;
+        comrak_options.extension.table = self.tables;
+        comrak_options.extension.autolink = self.autolinks;
+        comrak_options.extension.tasklist = self.task_lists;
+        comrak_options.extension.header_ids = self.header_id_prefix.clone();
+        comrak_options.extension.description_lists = self.description_lists;
+        comrak_options.extension.footnotes = self.footnotes;
+        comrak_options.extension.superscript = self.superscript;
+        comrak_options.extension.front_matter_delimiter = Some("---".to_string());
+        comrak_options.parse.smart = self.smart_punctuation;
+        comrak_options.render.hardbreaks = self.hard_breaks;
+        comrak_options.render.unsafe_ = self.unsafe_html;
+
+        comrak_options
+    }
 }
 
 /// Call a given function for the current and every possible child of the Markdown node.
@@ -47,21 +118,34 @@ where
 ///
 /// May arise from [`format_html_with_plugins`], returning a wrapped [`std::io::Error`].
 pub fn render_to_html(input: &str) -> Result<String> {
+    render_to_html_with(input, &MarkdownOptions::default())
+}
+
+/// Render a Markdown input to HTML using the given [`MarkdownOptions`].
+///
+/// # Errors
+///
+/// May arise from [`format_html_with_plugins`], returning a wrapped [`std::io::Error`].
+pub fn render_to_html_with(input: &str, options: &MarkdownOptions) -> Result<String> {
+    let comrak_options = options.comrak_options();
+
     // Create an arena for rendering purposes
     let arena = Arena::new();
-    let root = parse_document(&arena, input, &COMRAK_OPTIONS);
-
-    #[allow(clippy::match_single_binding)]
-    iter_nodes(root, &|node| match &mut node.data.borrow_mut().value {
-        _ => (),
+    let root = parse_document(&arena, input, &comrak_options);
+
+    // Comrak only gives the adapter the language of a code block, so give it the full info strings
+    let syntax_adapter = syntect_adapter::SyntectAdapter::new(SyntaxRegistry::shared());
+    iter_nodes(root, &|node| {
+        if let NodeValue::CodeBlock(code_block) = &node.data.borrow().value {
+            syntax_adapter.push_fence(&String::from_utf8_lossy(&code_block.info));
+        }
     });
 
     let mut comrak_plugins = ComrakPlugins::default();
-    let syntax_adapter = syntect_adapter::SyntectAdapter::new();
     comrak_plugins.render.codefence_syntax_highlighter = Some(&syntax_adapter);
 
     let mut html = vec![];
-    format_html_with_plugins(root, &COMRAK_OPTIONS, &mut html, &comrak_plugins)?;
+    format_html_with_plugins(root, &comrak_options, &mut html, &comrak_plugins)?;
 
     String::from_utf8(html).wrap_err("unable to decode html from utf8")
 }
@@ -71,6 +155,17 @@ pub fn render_to_html(input: &str) -> Result<String> {
 #[cfg_attr(feature = "tracing", tracing::instrument)]
 #[cfg(feature = "tokio")]
 pub async fn render_path_to_html(path: impl AsRef<Path> + std::fmt::Debug) -> Result<String> {
+    render_path_to_html_with(path, &MarkdownOptions::default()).await
+}
+
+/// Load a file from the filesystem and render the contents to HTML using the given
+/// [`MarkdownOptions`].
+#[cfg_attr(feature = "tracing", tracing::instrument)]
+#[cfg(feature = "tokio")]
+pub async fn render_path_to_html_with(
+    path: impl AsRef<Path> + std::fmt::Debug,
+    options: &MarkdownOptions,
+) -> Result<String> {
     #[cfg(feature = "tracing")]
     debug!("reading file");
 
@@ -79,17 +174,27 @@ pub async fn render_path_to_html(path: impl AsRef<Path> + std::fmt::Debug) -> Re
     #[cfg(feature = "tracing")]
     debug!("rendering HTML");
 
-    render_to_html(file_content.as_str())
+    render_to_html_with(file_content.as_str(), options)
 }
 
 /// Perform synchronous (blocking) functions.
 pub mod sync {
-    use super::{Path, Result, debug, render_to_html};
+    use super::{debug, render_to_html_with, MarkdownOptions, Path, Result};
 
     /// Load a file from the filesystem and render the the contents to HTML using opinionated
     /// Comrak definitions.
     #[cfg_attr(feature = "tracing", tracing::instrument)]
     pub fn render_path_to_html(path: impl AsRef<Path> + std::fmt::Debug) -> Result<String> {
+        render_path_to_html_with(path, &MarkdownOptions::default())
+    }
+
+    /// Load a file from the filesystem and render the contents to HTML using the given
+    /// [`MarkdownOptions`].
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    pub fn render_path_to_html_with(
+        path: impl AsRef<Path> + std::fmt::Debug,
+        options: &MarkdownOptions,
+    ) -> Result<String> {
         #[cfg(feature = "tracing")]
         debug!("reading file");
 
@@ -97,6 +202,6 @@ pub mod sync {
 
         #[cfg(feature = "tracing")]
         debug!("rendering HTML");
-        render_to_html(file_content.as_str())
+        render_to_html_with(file_content.as_str(), options)
     }
 }
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use eyre::WrapErr;
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+
+/// The registry used by every render, loaded once.
+static SHARED_REGISTRY: OnceLock<SyntaxRegistry> = OnceLock::new();
+
+/// Where syntax definitions are loaded from, and which language names are aliases of others.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyntaxOptions {
+    /// A syntax set dumped using `syntect::dumps::dump_to_file`, loaded instead of the default
+    /// syntaxes. The syntaxes must have been loaded with newlines included.
+    pub dump: Option<PathBuf>,
+    /// A directory searched recursively for `.sublime-syntax` files, which are added to the
+    /// loaded syntaxes.
+    pub directory: Option<PathBuf>,
+    /// Language names used by code blocks, mapped to the name of the language they are highlighted
+    /// as, such as `console` to `bash`.
+    pub aliases: HashMap<String, String>,
+}
+
+/// The syntax definitions used to highlight code blocks, alongside aliases of language names.
+#[derive(Debug)]
+pub struct SyntaxRegistry {
+    syntax_set: SyntaxSet,
+    aliases: HashMap<String, String>,
+}
+
+impl Default for SyntaxRegistry {
+    /// Load the default syntaxes, without any aliases.
+    fn default() -> Self {
+        SyntaxRegistry {
+            syntax_set: SyntaxSet::load_defaults_newlines(),
+            aliases: HashMap::new(),
+        }
+    }
+}
+
+impl SyntaxRegistry {
+    /// Load syntax definitions using the given [`SyntaxOptions`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error when the dump or a syntax definition can't be read or parsed.
+    pub fn load(options: &SyntaxOptions) -> eyre::Result<Self> {
+        let mut syntax_set = match &options.dump {
+            Some(path) => syntect::dumps::from_dump_file(path)
+                .wrap_err_with(|| format!("unable to load syntax dump {}", path.display()))?,
+            None => SyntaxSet::load_defaults_newlines(),
+        };
+
+        if let Some(directory) = &options.directory {
+            let mut builder = syntax_set.into_builder();
+            builder.add_from_folder(directory, true).wrap_err_with(|| {
+                format!("unable to load syntaxes from {}", directory.display())
+            })?;
+            syntax_set = builder.build();
+        }
+
+        Ok(SyntaxRegistry {
+            syntax_set,
+            aliases: options.aliases.clone(),
+        })
+    }
+
+    /// Returns the registry shared by every render. The default syntaxes are loaded the first time
+    /// it is used, unless another registry has been installed.
+    #[must_use]
+    pub fn shared() -> &'static SyntaxRegistry {
+        SHARED_REGISTRY.get_or_init(SyntaxRegistry::default)
+    }
+
+    /// Use this registry as the registry shared by every render.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error when the shared registry has already been used or installed.
+    pub fn install(self) -> eyre::Result<()> {
+        SHARED_REGISTRY
+            .set(self)
+            .map_err(|_| eyre::eyre!("the shared syntax registry is already in use"))
+    }
+
+    /// Returns every loaded syntax definition.
+    #[must_use]
+    pub fn syntax_set(&self) -> &SyntaxSet {
+        &self.syntax_set
+    }
+
+    /// Find the syntax definition for a language name or file extension, following any alias.
+    #[must_use]
+    pub fn find_syntax(&self, language: &str) -> Option<&SyntaxReference> {
+        let language = self.aliases.get(language).map_or(language, String::as_str);
+        self.syntax_set.find_syntax_by_token(language)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finding_syntaxes() {
+        let registry = SyntaxRegistry::load(&SyntaxOptions {
+            aliases: HashMap::from([("console".to_string(), "bash".to_string())]),
+            ..SyntaxOptions::default()
+        })
+        .unwrap();
+        assert_eq!(
+            registry.find_syntax("console").unwrap().name,
+            "Bourne Again Shell (bash)"
+        );
+        assert_eq!(registry.find_syntax("rs").unwrap().name, "Rust");
+        assert!(registry.find_syntax("example").is_none());
+    }
+
+    #[test]
+    fn loading_syntaxes_from_a_directory() {
+        let registry = SyntaxRegistry::load(&SyntaxOptions {
+            directory: Some(concat!(env!("CARGO_MANIFEST_DIR"), "/src/test_data/syntaxes").into()),
+            ..SyntaxOptions::default()
+        })
+        .unwrap();
+        assert_eq!(registry.find_syntax("example").unwrap().name, "Example");
+        assert!(registry.find_syntax("rust").is_some());
+
+        let missing = SyntaxRegistry::load(&SyntaxOptions {
+            directory: Some(concat!(env!("CARGO_MANIFEST_DIR"), "/src/test_data/missing").into()),
+            ..SyntaxOptions::default()
+        });
+        assert!(missing.is_err());
+    }
+}
@@ -1,69 +1,343 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
 use comrak::adapters::SyntaxHighlighterAdapter;
 use syntect::{
-    html::{ClassStyle, ClassedHTMLGenerator},
-    parsing::SyntaxSet,
+    html::line_tokens_to_classed_spans,
+    parsing::{ParseState, ScopeStack, ScopeStackOp, SyntaxReference},
     util::LinesWithEndings,
 };
 
-/// A Syntect adapter plugin for Comrak to use a [`ClassedHTMLGenerator`].
+use crate::{fence::FenceInfo, SyntaxRegistry, CLASS_STYLE};
+
+/// Escape text to be included in HTML.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+/// A Syntect adapter plugin for Comrak to use classed HTML, as produced by a
+/// [`syntect::html::ClassedHTMLGenerator`].
+///
+/// Comrak only gives the adapter the first word of the info string of a code fence, so the info
+/// strings of every code block are given to the adapter using [`SyntectAdapter::push_fence`] before
+/// rendering, in the order the code blocks are rendered in.
 #[derive(Debug, Clone)]
-pub(crate) struct SyntectAdapter {
-    syntax_set: SyntaxSet,
+pub(crate) struct SyntectAdapter<'a> {
+    registry: &'a SyntaxRegistry,
+    /// The info strings of the code blocks that haven't been rendered yet.
+    fences: RefCell<VecDeque<FenceInfo>>,
+    /// The number of the code block being rendered, counted from 1, and its info string.
+    current: RefCell<(usize, FenceInfo)>,
 }
 
-impl SyntectAdapter {
-    pub(crate) fn new() -> SyntectAdapter {
+impl<'a> SyntectAdapter<'a> {
+    pub(crate) fn new(registry: &'a SyntaxRegistry) -> SyntectAdapter<'a> {
         SyntectAdapter {
-            syntax_set: SyntaxSet::load_defaults_newlines(),
+            registry,
+            fences: RefCell::default(),
+            current: RefCell::default(),
         }
     }
+
+    /// Add the info string of the next code block to be rendered.
+    pub(crate) fn push_fence(&self, info: &str) {
+        self.fences.borrow_mut().push_back(FenceInfo::parse(info));
+    }
+
+    /// Highlight every line of a code block, closing every `<span>` at the end of each line and
+    /// opening them again at the start of the next line, so lines can be wrapped individually.
+    ///
+    /// Returns `None` if Syntect is unable to highlight any line.
+    fn highlight_lines(
+        &self,
+        syntax_reference: &SyntaxReference,
+        lines: &[&str],
+    ) -> Option<Vec<String>> {
+        let mut parse_state = ParseState::new(syntax_reference);
+        let mut scope_stack = ScopeStack::new();
+        lines
+            .iter()
+            .map(|line| {
+                let reopen = scope_stack
+                    .as_slice()
+                    .iter()
+                    .map(|scope| (0, ScopeStackOp::Push(*scope)))
+                    .collect::<Vec<_>>();
+                let (mut html, _) =
+                    line_tokens_to_classed_spans("", &reopen, CLASS_STYLE, &mut ScopeStack::new())
+                        .ok()?;
+                let ops = parse_state
+                    .parse_line(line, self.registry.syntax_set())
+                    .ok()?;
+                let (line_html, _) =
+                    line_tokens_to_classed_spans(line, &ops, CLASS_STYLE, &mut scope_stack).ok()?;
+                html.push_str(&line_html);
+                html.push_str(&"</span>".repeat(scope_stack.len()));
+                Some(html)
+            })
+            .collect()
+    }
 }
 
-impl SyntaxHighlighterAdapter for SyntectAdapter {
-    /// Given an optional language and a code block, highlight the code block using a
-    /// [`ClassedHTMLGenerator`]. The produced classes have a "syntect-" prefix.
+impl SyntaxHighlighterAdapter for SyntectAdapter<'_> {
+    /// Given an optional language and a code block, highlight the code block using classes with a
+    /// "syntect-" prefix. The language in the info string of the code fence is preferred over the
+    /// language given by Comrak, which is only used when the info string has no options.
+    ///
+    /// When the code fence has line options, such as `rust {3,5-7} linenos start=40`, every line is
+    /// wrapped in a `<span class="code_line">` with an ID such as `code-1-L42` that can be linked
+    /// to. The `code_line_highlight` class is added to highlighted lines, and line numbers are
+    /// links to their line.
+    ///
+    /// Languages such as `diff-rust` highlight a diff of code in the inner language. Lines starting
+    /// with `+` or `-` are given the `code_line_added` or `code_line_removed` class, and the rest
+    /// of every line is highlighted as the inner language.
     ///
     /// If Syntect is unable to highlight a section of code, or is unable to determine the language
-    /// the code has been written in, it will instead return the unmodified code as a string.
+    /// the code has been written in, the code is escaped but otherwise left unmodified.
     fn highlight(&self, lang: Option<&str>, code: &str) -> String {
+        let current = self.current.borrow();
+        let (block, fence) = (current.0, &current.1);
+
+        let syntax_set = self.registry.syntax_set();
+        let diff_language = fence.diff_language();
+        // Comrak's language is the first word of the info string, which may be an option
+        let lang = diff_language
+            .or(fence.language.as_deref())
+            .or_else(|| lang.filter(|_| !fence.has_options()))
+            .filter(|lang| !lang.is_empty());
         let syntax_reference = if let Some(lang_name) = lang {
-            self.syntax_set.find_syntax_by_token(lang_name)
+            self.registry.find_syntax(lang_name)
         } else {
-            let Some(line) = code.lines().next() else {
-                return code.to_string()
-            };
-            self.syntax_set
-                .find_syntax_by_first_line(format!("{line}\n").as_str())
+            code.lines()
+                .next()
+                .and_then(|line| syntax_set.find_syntax_by_first_line(format!("{line}\n").as_str()))
         };
 
-        let Some(syntax_reference) = syntax_reference else {
-            return code.to_string()
+        // The first character of every line of a diff is a marker, and the rest of the line is code
+        let mut lines = LinesWithEndings::from(code).collect::<Vec<_>>();
+        let markers = if diff_language.is_some() {
+            lines
+                .iter_mut()
+                .map(|line| {
+                    let marker = if line.starts_with(['+', '-', ' ']) {
+                        &line[..1]
+                    } else {
+                        ""
+                    };
+                    *line = &line[marker.len()..];
+                    marker
+                })
+                .collect()
+        } else {
+            vec![""; lines.len()]
         };
 
-        let mut html_generator = ClassedHTMLGenerator::new_with_class_style(
-            syntax_reference,
-            &self.syntax_set,
-            ClassStyle::SpacedPrefixed { prefix: "syntect-" },
-        );
-        for line in LinesWithEndings::from(code) {
-            match html_generator.parse_html_for_line_which_includes_newline(line) {
-                Ok(_) => (),
-                Err(_) => return code.to_string(),
-            }
+        let rendered = syntax_reference
+            .and_then(|syntax_reference| self.highlight_lines(syntax_reference, &lines))
+            .unwrap_or_else(|| lines.iter().map(|line| escape(line)).collect());
+
+        if !fence.wraps_lines() {
+            return rendered.concat();
         }
 
-        html_generator.finalize()
+        let mut html = String::new();
+        for (index, (marker, line)) in markers.iter().zip(&rendered).enumerate() {
+            let line_number = fence.first_line() + index;
+            let id = format!("code-{block}-L{line_number}");
+            let mut classes = "code_line".to_string();
+            let highlighted = fence
+                .highlight_lines
+                .iter()
+                .any(|lines| lines.contains(&(index + 1)));
+            if highlighted {
+                classes.push_str(" code_line_highlight");
+            }
+            match *marker {
+                "+" => classes.push_str(" code_line_added"),
+                "-" => classes.push_str(" code_line_removed"),
+                _ => (),
+            }
+            html.push_str(&format!("<span class=\"{classes}\" id=\"{id}\">"));
+            if fence.line_numbers {
+                html.push_str(&format!(
+                    "<a class=\"code_line_number\" href=\"#{id}\" data-line=\"{line_number}\"></a>"
+                ));
+            }
+            html.push_str(marker);
+            html.push_str(line);
+            html.push_str("</span>");
+        }
+        html
     }
 
+    /// Start rendering the next code block, using the next info string given to
+    /// [`SyntectAdapter::push_fence`]. The title and the language of the code block are shown in a
+    /// `<div class="code_header">` before the `<pre>` tag.
     fn build_pre_tag(&self, attributes: &std::collections::HashMap<String, String>) -> String {
+        let fence = self.fences.borrow_mut().pop_front().unwrap_or_default();
+
+        let mut html = String::new();
+        if fence.title.is_some() || fence.language.is_some() {
+            html.push_str("<div class=\"code_header\">");
+            if let Some(title) = &fence.title {
+                html.push_str(&format!(
+                    "<span class=\"code_title\">{}</span>",
+                    escape(title)
+                ));
+            }
+            if let Some(language) = &fence.language {
+                html.push_str(&format!(
+                    "<span class=\"code_language\">{}</span>",
+                    escape(language)
+                ));
+            }
+            html.push_str("</div>");
+        }
+
+        let mut current = self.current.borrow_mut();
+        *current = (current.0 + 1, fence);
+
+        // The generated stylesheet sets the background and text colors of the theme on this class.
         if let Some(lang) = attributes.get("lang") {
-            format!("<pre lang=\"{lang}\">")
+            html.push_str(&format!(
+                "<pre class=\"syntect-code\" lang=\"{}\">",
+                escape(lang)
+            ));
         } else {
-            "<pre>".to_string()
+            html.push_str("<pre class=\"syntect-code\">");
         }
+        html
     }
 
     fn build_code_tag(&self, _attributes: &std::collections::HashMap<String, String>) -> String {
         "<code>".to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Render a code block the way Comrak does, giving the adapter the first word of the info
+    /// string as the language.
+    fn render(adapter: &SyntectAdapter, info: &str, code: &str) -> String {
+        adapter.push_fence(info);
+        let lang = info.split_whitespace().next();
+        let attributes = lang
+            .map(|lang| ("lang".to_string(), lang.to_string()))
+            .into_iter()
+            .collect();
+        adapter.build_pre_tag(&attributes)
+            + &adapter.build_code_tag(&attributes)
+            + &adapter.highlight(lang, code)
+    }
+
+    /// Returns the contents of every `<span class="code_line">`.
+    fn code_lines(html: &str) -> Vec<&str> {
+        html.split("<span class=\"code_line")
+            .skip(1)
+            .map(|line| line.split_once('>').unwrap().1)
+            .collect()
+    }
+
+    #[test]
+    fn highlighting_lines() {
+        let adapter = SyntectAdapter::new(SyntaxRegistry::shared());
+        let code = "/* a\n b */\nfn main() {}\n";
+        let plain = render(&adapter, "rust", code);
+        assert!(plain.starts_with(
+            "<div class=\"code_header\"><span class=\"code_language\">rust</span></div>\
+             <pre class=\"syntect-code\" lang=\"rust\"><code>\
+             <span class=\"syntect-source syntect-rust\">"
+        ));
+        assert!(!plain.contains("code_line"));
+        assert_eq!(
+            plain.matches("<span").count(),
+            plain.matches("</span>").count()
+        );
+
+        let html = render(&adapter, "rust {2} linenos start=41", code);
+        assert!(html.contains(
+            "<span class=\"code_line\" id=\"code-2-L41\">\
+             <a class=\"code_line_number\" href=\"#code-2-L41\" data-line=\"41\"></a>"
+        ));
+        assert!(html.contains("<span class=\"code_line code_line_highlight\" id=\"code-2-L42\">"));
+        assert!(html.contains("id=\"code-2-L43\""));
+        assert!(!html.contains("id=\"code-2-L44\""));
+
+        // The block comment is closed at the end of the first line and reopened on the second
+        let lines = code_lines(&html);
+        assert_eq!(lines.len(), 3);
+        for line in lines {
+            assert_eq!(
+                line.matches("<span").count() + 1,
+                line.matches("</span>").count()
+            );
+        }
+        assert!(code_lines(&html)[1].contains("syntect-comment"));
+    }
+
+    #[test]
+    fn highlighting_without_a_language() {
+        let adapter = SyntectAdapter::new(SyntaxRegistry::shared());
+        let html = render(&adapter, "", "<b>\n");
+        assert_eq!(html, "<pre class=\"syntect-code\"><code>&lt;b&gt;\n");
+
+        // Options aren't mistaken for a language, and the first line is still used
+        let html = render(&adapter, "{1}", "#!/bin/bash\necho\n");
+        assert!(!html.contains("code_header"));
+        assert!(html.contains("syntect-shell"));
+        assert!(html.contains("code_line_highlight"));
+    }
+
+    #[test]
+    fn highlighting_diffs() {
+        let adapter = SyntectAdapter::new(SyntaxRegistry::shared());
+        let html = render(
+            &adapter,
+            "diff-rust",
+            " fn a() {}\n-fn b() {}\n+fn c() {}\n",
+        );
+        assert!(html.contains("<span class=\"code_language\">diff-rust</span>"));
+        let lines = code_lines(&html);
+        assert!(lines[0].starts_with(" <span class=\"syntect-source syntect-rust\">"));
+        assert!(lines[1].starts_with("-<span class=\"syntect-source syntect-rust\">"));
+        assert!(lines[2].starts_with("+<span class=\"syntect-source syntect-rust\">"));
+        assert!(lines.iter().all(|line| line.contains("syntect-storage")));
+        assert!(html.contains("<span class=\"code_line\" id=\"code-1-L1\">"));
+        assert!(html.contains("<span class=\"code_line code_line_removed\" id=\"code-1-L2\">"));
+        assert!(html.contains("<span class=\"code_line code_line_added\" id=\"code-1-L3\">"));
+
+        // Unknown languages are escaped, but still marked as added or removed
+        let html = render(&adapter, "diff-foo", "-<a>\n+<b>\n");
+        assert!(html
+            .contains("<span class=\"code_line code_line_removed\" id=\"code-2-L1\">-&lt;a&gt;\n"));
+        assert!(html
+            .contains("<span class=\"code_line code_line_added\" id=\"code-2-L2\">+&lt;b&gt;\n"));
+        assert!(!html.contains("syntect-source"));
+    }
+
+    #[test]
+    fn escaping_info_strings() {
+        let adapter = SyntectAdapter::new(SyntaxRegistry::shared());
+        let html = render(&adapter, "title=\"a<b\"", "");
+        assert!(html.starts_with(
+            "<div class=\"code_header\"><span class=\"code_title\">a&lt;b</span></div>"
+        ));
+
+        let html = render(&adapter, "a\"><script>", "");
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("<pre class=\"syntect-code\" lang=\"a&quot;&gt;&lt;script&gt;\">"));
+    }
+}
@@ -0,0 +1,83 @@
+use std::path::Path;
+
+use eyre::WrapErr;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::css_for_theme_with_class_style;
+
+use crate::CLASS_STYLE;
+
+/// The bundled theme used for code blocks when the reader prefers a light color scheme.
+pub const DEFAULT_LIGHT_THEME: &str = "InspiredGitHub";
+
+/// The bundled theme used for code blocks when the reader prefers a dark color scheme.
+pub const DEFAULT_DARK_THEME: &str = "base16-ocean.dark";
+
+/// Load a theme bundled with Syntect by name, or a `.tmTheme` file by path.
+fn load_theme(theme: &str) -> eyre::Result<Theme> {
+    if let Some(bundled) = ThemeSet::load_defaults().themes.remove(theme) {
+        return Ok(bundled);
+    }
+    ThemeSet::get_theme(Path::new(theme)).wrap_err_with(|| {
+        let mut bundled = ThemeSet::load_defaults()
+            .themes
+            .into_keys()
+            .collect::<Vec<_>>();
+        bundled.sort();
+        format!(
+            "{theme} is neither a bundled theme ({}) nor a readable .tmTheme file",
+            bundled.join(", ")
+        )
+    })
+}
+
+/// Generate the CSS for a single theme, scoped to a `prefers-color-scheme` media query.
+fn themed_css(theme: &str, scheme: &str) -> eyre::Result<String> {
+    let css = css_for_theme_with_class_style(&load_theme(theme)?, CLASS_STYLE)
+        .wrap_err_with(|| format!("unable to generate CSS for {theme}"))?;
+    Ok(format!(
+        "@media (prefers-color-scheme: {scheme}) {{\n{css}}}\n"
+    ))
+}
+
+/// Generate the stylesheet for the classes given to highlighted code blocks, using `light` when
+/// the reader prefers a light color scheme and `dark` when they prefer a dark color scheme. Each
+/// theme is either the name of a theme bundled with Syntect, such as [`DEFAULT_LIGHT_THEME`], or
+/// the path of a `.tmTheme` file.
+///
+/// # Errors
+///
+/// Returns an error when a theme is not bundled and can't be read or parsed.
+pub fn syntax_stylesheet(light: &str, dark: &str) -> eyre::Result<String> {
+    Ok(themed_css(light, "light")? + "\n" + &themed_css(dark, "dark")?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generating_stylesheets() {
+        let css = syntax_stylesheet(DEFAULT_LIGHT_THEME, DEFAULT_DARK_THEME).unwrap();
+        let (light, dark) = css
+            .split_once("\n@media (prefers-color-scheme: dark) {\n")
+            .unwrap();
+        assert!(light.starts_with("@media (prefers-color-scheme: light) {\n"));
+        assert!(light.ends_with("}\n"));
+        assert!(dark.ends_with("}\n"));
+        for theme in [light, dark] {
+            assert!(theme.contains(".syntect-code {"));
+            assert!(theme.contains(".syntect-comment"));
+            assert!(!theme.contains(".code {"));
+        }
+        assert_ne!(light, dark);
+    }
+
+    #[test]
+    fn loading_unknown_themes() {
+        let error = syntax_stylesheet("Not A Theme", DEFAULT_DARK_THEME).unwrap_err();
+        let message = error.to_string();
+        assert!(message.starts_with("Not A Theme is neither a bundled theme ("));
+        assert!(message.contains(DEFAULT_LIGHT_THEME));
+        assert!(syntax_stylesheet(DEFAULT_LIGHT_THEME, "").is_err());
+    }
+}
@@ -0,0 +1,242 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+
+use chrono::{DateTime, Utc};
+use clap::Args;
+use color_eyre::eyre::{bail, Result, WrapErr};
+use tracing::{debug, info};
+
+use crate::snippet::{write_snippet, SnippetMetadata, SNIPPETS_DIRECTORY};
+
+/// Run a command inside a pseudo-terminal, saving its output and metadata as an output snippet
+#[derive(Clone, Debug, Args)]
+pub(crate) struct CaptureArgs {
+    /// The slug of the post the snippet belongs to
+    #[arg(long)]
+    pub(crate) post: String,
+
+    /// The name of the snippet file, within the snippet directory of the post
+    #[arg(long, short)]
+    pub(crate) output: PathBuf,
+
+    /// The width of the pseudo-terminal, in columns
+    #[arg(long, default_value_t = 100)]
+    pub(crate) columns: u16,
+
+    /// The height of the pseudo-terminal, in rows
+    #[arg(long, default_value_t = 30)]
+    pub(crate) rows: u16,
+
+    /// The command to run, and its arguments, after `--`
+    #[arg(last = true, required = true)]
+    pub(crate) command: Vec<String>,
+}
+
+/// Quote an argument for a POSIX shell, leaving arguments that don't need quoting as they are.
+fn shell_quote(argument: &str) -> String {
+    let safe = !argument.is_empty()
+        && argument
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=+,@%".contains(c));
+    if safe {
+        argument.to_string()
+    } else {
+        format!("'{}'", argument.replace('\'', "'\\''"))
+    }
+}
+
+/// Shorten a path within the home directory to start with `~`, so that snippets don't show where
+/// the home directory of whoever captured them is.
+fn display_directory(directory: &Path, home: Option<&Path>) -> String {
+    match home.and_then(|home| directory.strip_prefix(home).ok()) {
+        Some(relative) if relative.as_os_str().is_empty() => "~".to_string(),
+        Some(relative) => format!("~/{}", relative.display()),
+        None => directory.display().to_string(),
+    }
+}
+
+/// Open a pseudo-terminal with the given size, returning the controlling side and the terminal
+/// side.
+fn open_pty(columns: u16, rows: u16) -> io::Result<(OwnedFd, OwnedFd)> {
+    let (mut controller, mut terminal) = (-1, -1);
+    let mut size = libc::winsize {
+        ws_row: rows,
+        ws_col: columns,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // SAFETY: the pointers are valid for the duration of the call, and a null name and terminal
+    // settings are allowed
+    let result = unsafe {
+        libc::openpty(
+            &mut controller,
+            &mut terminal,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::addr_of_mut!(size),
+        )
+    };
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // Keep newlines as they were written, rather than translating them to CRLF
+    let mut settings = std::mem::MaybeUninit::<libc::termios>::uninit();
+    // SAFETY: the terminal side was just opened, and the settings are only read once initialized
+    unsafe {
+        if libc::tcgetattr(terminal, settings.as_mut_ptr()) == 0 {
+            let mut settings = settings.assume_init();
+            settings.c_oflag &= !libc::ONLCR;
+            libc::tcsetattr(terminal, libc::TCSANOW, &settings);
+        }
+    }
+    // SAFETY: openpty succeeded, so both descriptors are open and owned by nothing else
+    unsafe {
+        Ok((
+            OwnedFd::from_raw_fd(controller),
+            OwnedFd::from_raw_fd(terminal),
+        ))
+    }
+}
+
+/// Run a command with a pseudo-terminal as its standard streams and controlling terminal,
+/// returning everything it wrote and its exit status. Output is also echoed to standard output when
+/// `echo` is set.
+pub(crate) fn run_in_pty(
+    mut command: Command,
+    columns: u16,
+    rows: u16,
+    echo: bool,
+) -> Result<(Vec<u8>, ExitStatus)> {
+    let (controller, terminal) = open_pty(columns, rows).wrap_err("Unable to open a pty")?;
+    command
+        .stdin(Stdio::from(terminal.try_clone()?))
+        .stdout(Stdio::from(terminal.try_clone()?))
+        .stderr(Stdio::from(terminal))
+        .env("TERM", "xterm-256color")
+        .env("COLUMNS", columns.to_string())
+        .env("LINES", rows.to_string());
+    // SAFETY: only async-signal-safe functions are called between fork and exec
+    unsafe {
+        command.pre_exec(|| {
+            // Start a new session, so that the pty becomes the controlling terminal
+            if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut child = command.spawn().wrap_err("Unable to run command")?;
+
+    // The terminal side must only be open in the child, otherwise reading never ends
+    drop(command);
+
+    let mut controller = File::from(controller);
+    let mut output = vec![];
+    let mut buffer = [0; 4096];
+    let mut stdout = io::stdout();
+    loop {
+        match controller.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(length) => {
+                if echo {
+                    stdout.write_all(&buffer[..length])?;
+                    stdout.flush()?;
+                }
+                output.extend_from_slice(&buffer[..length]);
+            }
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            // Linux reports the terminal side closing as an I/O error
+            Err(error) if error.raw_os_error() == Some(libc::EIO) => break,
+            Err(error) => return Err(error.into()),
+        }
+    }
+
+    Ok((output, child.wait()?))
+}
+
+/// Returns the exit code of a process, following the shell convention of 128 plus the signal
+/// number for processes killed by a signal.
+pub(crate) fn exit_code(status: ExitStatus) -> i32 {
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(-1)
+}
+
+#[tracing::instrument]
+pub(crate) fn capture(args: &CaptureArgs) -> Result<()> {
+    let Some((program, arguments)) = args.command.split_first() else {
+        bail!("No command was given to capture");
+    };
+    let relative_path = Path::new(&args.post).join(&args.output);
+    if !relative_path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        bail!("The snippet must be within the snippet directory of the post");
+    }
+    let path = Path::new(SNIPPETS_DIRECTORY).join(relative_path);
+
+    let cwd = std::env::current_dir()?;
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let command_line = args
+        .command
+        .iter()
+        .map(|argument| shell_quote(argument))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    info!(?command_line, "capturing command output");
+    let captured_at = DateTime::<Utc>::from(std::time::SystemTime::now());
+    let mut command = Command::new(program);
+    command.args(arguments);
+    let (output, status) = run_in_pty(command, args.columns, args.rows, true)?;
+
+    let metadata = SnippetMetadata {
+        command: Some(command_line),
+        cwd: Some(display_directory(&cwd, home.as_deref())),
+        exit_code: Some(exit_code(status)),
+        captured_at: Some(captured_at),
+        columns: Some(args.columns),
+        rows: Some(args.rows),
+        ..Default::default()
+    };
+    write_snippet(&path, &metadata, &output)?;
+    debug!(?path, ?status, "saved output snippet");
+    eprintln!(
+        "\nSaved to {}, reference it with <opaque-ansi-output source=\"{}\" relative>",
+        path.display(),
+        args.output.display()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoting_arguments() {
+        assert_eq!(shell_quote("--color=always"), "--color=always");
+        assert_eq!(shell_quote("hello world"), "'hello world'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn shortening_home_directories() {
+        let home = Path::new("/home/ryan");
+        assert_eq!(display_directory(home, Some(home)), "~");
+        assert_eq!(
+            display_directory(Path::new("/home/ryan/opaque"), Some(home)),
+            "~/opaque"
+        );
+        assert_eq!(display_directory(Path::new("/tmp"), Some(home)), "/tmp");
+    }
+}
@@ -1,14 +1,35 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use serde::{Deserialize, Serialize};
 
-use crate::state::Author;
+use crate::capture::CaptureArgs;
+use crate::state::{Author, MarkdownConfig};
+use crate::verify::VerifyArgs;
 
 fn default_config_file() -> PathBuf {
     "config.yaml".into()
 }
 
+/// A task to run instead of serving the blog
+#[derive(Clone, Debug, Subcommand)]
+pub(crate) enum Command {
+    Capture(CaptureArgs),
+
+    /// Manage output snippets
+    Snippets {
+        #[command(subcommand)]
+        command: SnippetsCommand,
+    },
+}
+
+/// A task for managing output snippets
+#[derive(Clone, Debug, Subcommand)]
+pub(crate) enum SnippetsCommand {
+    Verify(VerifyArgs),
+}
+
 // This can be loaded from a configuration file and command line and then flattened into the
 // resulting Config
 
@@ -43,4 +64,45 @@ pub(crate) struct PartialConfig {
     /// The address that the server will be bound to
     #[arg(long, short)]
     pub(crate) bind_address: Option<std::net::SocketAddr>,
+
+    /// The built-in terminal color theme used for ANSI terminal output, replacing the 16 base
+    /// colors of the site theme on pages
+    #[arg(long)]
+    pub(crate) terminal_theme: Option<String>,
+
+    /// A file containing a custom terminal color palette, overriding the terminal theme
+    #[arg(long)]
+    pub(crate) terminal_palette: Option<PathBuf>,
+
+    /// A syntax set dumped by syntect, used to highlight code blocks instead of the default syntaxes
+    #[arg(long)]
+    pub(crate) syntax_dump: Option<PathBuf>,
+
+    /// A directory containing extra `.sublime-syntax` files used to highlight code blocks
+    #[arg(long)]
+    pub(crate) syntax_directory: Option<PathBuf>,
+
+    /// The syntax highlighting theme used when the reader prefers a light color scheme, either a
+    /// theme bundled with syntect or the path of a `.tmTheme` file
+    #[arg(long)]
+    pub(crate) syntax_theme_light: Option<String>,
+
+    /// The syntax highlighting theme used when the reader prefers a dark color scheme, either a
+    /// theme bundled with syntect or the path of a `.tmTheme` file
+    #[arg(long)]
+    pub(crate) syntax_theme_dark: Option<String>,
+
+    /// Languages of code blocks mapped to the language they are highlighted as, which can only be
+    /// set in the configuration file
+    #[arg(skip)]
+    pub(crate) syntax_aliases: Option<HashMap<String, String>>,
+
+    /// Markdown extensions to enable or disable, which can only be set in the configuration file
+    #[arg(skip)]
+    pub(crate) markdown: Option<MarkdownConfig>,
+
+    /// A task to run instead of serving the blog, which is never loaded from the configuration file
+    #[command(subcommand)]
+    #[serde(skip)]
+    pub(crate) command: Option<Command>,
 }
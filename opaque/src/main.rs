@@ -9,13 +9,17 @@ use tracing_subscriber::fmt::format::FmtSpan;
 use tracing_subscriber::prelude::*;
 
 use axum::{routing::get, Extension, Router};
+use clap::Parser;
 
+mod capture;
 mod cli;
 mod state;
 
 mod pages;
 mod post_scanner;
 mod postprocessing;
+mod snippet;
+mod verify;
 
 fn setup_registry() {
     let envfilter = EnvFilter::builder()
@@ -34,6 +38,14 @@ async fn main() -> Result<(), Report> {
 
     color_eyre::install()?;
 
+    match cli::PartialConfig::parse().command {
+        Some(cli::Command::Capture(args)) => return capture::capture(&args),
+        Some(cli::Command::Snippets {
+            command: cli::SnippetsCommand::Verify(args),
+        }) => return verify::verify(&args),
+        None => (),
+    }
+
     // TODO: dynamic generation of either `app` or `page_map`?
     // I have not seen other projects do this so it may be fine to just leave it as-is. Besides,
     // this gives me the ability to add arbitrary URLs.
@@ -51,12 +63,20 @@ async fn main() -> Result<(), Report> {
 
     info!(?state.config, "Running with given configuration");
 
+    opaque_markdown::SyntaxRegistry::load(&state.config.syntax_options())
+        .wrap_err("Unable to load syntax definitions")?
+        .install()?;
+
     let addr = state.config.bind_address;
 
     let app = Router::new()
         .route("/", get(pages::index))
         .route("/posts", get(pages::post::index))
         .route("/posts/:post", get(pages::post::slug))
+        .route("/posts/:post/snippets/*path", get(pages::snippets::download))
+        .route("/terminal.css", get(pages::assets::terminal_stylesheet))
+        .route("/syntax.css", get(pages::assets::syntax_stylesheet))
+        .route("/snippets/*path", get(pages::snippets::snippet))
         .route(
             format!(
                 "/{}/*path",
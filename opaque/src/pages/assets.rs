@@ -1,14 +1,19 @@
+use std::sync::Arc;
+
 use axum::extract::Path;
 #[allow(unused_imports)]
 use axum::{
     body::{boxed, Empty, Full},
     http::StatusCode,
     response::{IntoResponse, Response},
+    Extension,
 };
 
 #[cfg(feature = "bundled_static")]
 use include_dir::{include_dir, Dir};
 
+use crate::state::State;
+
 #[cfg(feature = "bundled_static")]
 static STATIC_DIR: Dir<'_> = include_dir!("static");
 
@@ -42,3 +47,27 @@ pub(crate) async fn static_path(Path(_path): axum::extract::Path<String>) -> imp
         .body(boxed(Empty::new()))
         .expect("unable to build 404 body")
 }
+
+#[allow(clippy::unused_async)]
+pub(crate) async fn terminal_stylesheet(state: Extension<Arc<State>>) -> impl IntoResponse {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(
+            axum::http::header::CONTENT_TYPE,
+            axum::http::HeaderValue::from_static("text/css"),
+        )
+        .body(boxed(Full::from(state.terminal_stylesheet.clone())))
+        .expect("unable to serve terminal stylesheet")
+}
+
+#[allow(clippy::unused_async)]
+pub(crate) async fn syntax_stylesheet(state: Extension<Arc<State>>) -> impl IntoResponse {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(
+            axum::http::header::CONTENT_TYPE,
+            axum::http::HeaderValue::from_static("text/css"),
+        )
+        .body(boxed(Full::from(state.syntax_stylesheet.clone())))
+        .expect("unable to serve syntax stylesheet")
+}
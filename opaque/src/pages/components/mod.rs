@@ -13,7 +13,8 @@ pub(crate) fn head(page_title: &str) -> Markup {
             meta charset="utf-8";
             meta name="viewport" content="width=device-width, initial-scale=1";
             link rel="stylesheet" href="/static/assets/main.css";
-            link rel="stylesheet" href="/static/assets/syntect.css";
+            link rel="stylesheet" href="/terminal.css";
+            link rel="stylesheet" href="/syntax.css";
             title {
                 (page_title)
             }
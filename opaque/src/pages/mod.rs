@@ -8,7 +8,7 @@ use axum::{
 };
 use maud::{html, Markup, PreEscaped, DOCTYPE};
 
-use opaque_markdown::render_path_to_html;
+use opaque_markdown::render_path_to_html_with;
 
 use crate::state::State;
 
@@ -16,12 +16,16 @@ pub(crate) mod assets;
 pub(crate) mod components;
 
 pub(crate) mod post;
+pub(crate) mod snippets;
 
 #[derive(thiserror::Error, Debug)]
 pub(crate) enum Error {
     #[error("blog post not found: {0}")]
     PostNotFound(String),
 
+    #[error("output snippet not found: {0}")]
+    SnippetNotFound(String),
+
     #[error("the server had an internal error: {0}")]
     InternalServerError(String),
 }
@@ -47,7 +51,7 @@ impl IntoResponse for Error {
 #[tracing::instrument(skip(state))]
 pub(crate) async fn index(state: Extension<Arc<State>>) -> Result<Markup> {
     let path = "content/about.md";
-    let content = render_path_to_html(path).await?;
+    let content = render_path_to_html_with(path, &state.config.markdown_options(None)).await?;
     Ok(html! {
         (DOCTYPE)
         html {
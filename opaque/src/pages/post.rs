@@ -6,7 +6,7 @@ use std::sync::OnceLock;
 use tokio::sync::Mutex;
 use tracing::debug;
 
-use opaque_markdown::render_path_to_html;
+use opaque_markdown::render_path_to_html_with;
 
 use super::{components, Error, Result};
 use crate::postprocessing::PostProcessingBuilder;
@@ -64,8 +64,9 @@ pub(crate) async fn slug(Path(post_slug): Path<String>, state: Extension<Arc<Sta
             hit.clone()
         }
         None => {
+            let options = state.config.markdown_options(Some(&post.front_matter));
             #[allow(clippy::let_and_return)] 
-            let content = render_path_to_html(post.file_path.as_path()).await?;
+            let content = render_path_to_html_with(post.file_path.as_path(), &options).await?;
             #[cfg(feature = "cache")]
             cache.insert((post_slug.clone(), content.clone()));
             content
@@ -94,6 +95,7 @@ pub(crate) async fn slug(Path(post_slug): Path<String>, state: Extension<Arc<Sta
             "opaque-ansi-output".to_string(),
             "output_snippets/".to_string(),
             post_slug,
+            state.palette.clone(),
         )
         .unwrap()
         .build();
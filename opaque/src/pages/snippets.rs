@@ -0,0 +1,225 @@
+use std::path::{Component, PathBuf};
+use std::sync::Arc;
+
+use axum::{
+    body::{boxed, Full},
+    extract::{Path, Query},
+    http::StatusCode,
+    response::Response,
+    Extension,
+};
+use serde::Deserialize;
+use tracing::debug;
+
+use opaque_ansi::palette::Palette;
+use opaque_ansi::render::{PlainTextRenderer, Renderer};
+use opaque_ansi::{parse_styled_with, rewrite_ansi_to_svg, RenderOptions, SvgOptions};
+
+use super::{Error, Result};
+use crate::snippet::{split_metadata, SNIPPETS_DIRECTORY};
+use crate::state::State;
+
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct SvgQuery {
+    /// The width of the terminal the output is replayed into
+    columns: Option<usize>,
+    /// Whether to draw a window title bar, defaulting to true
+    chrome: Option<bool>,
+    /// The title of the window, defaulting to the name of the file
+    title: Option<String>,
+}
+
+/// The format an output snippet is downloaded in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum DownloadFormat {
+    /// The output as it was captured, including escape sequences
+    #[default]
+    Raw,
+    /// The output as it would be displayed, without any escape sequences
+    Plain,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct DownloadQuery {
+    #[serde(default)]
+    format: DownloadFormat,
+}
+
+/// Resolve a path within the snippets directory, refusing any path that could escape it.
+fn snippet_path(path: &str) -> Option<PathBuf> {
+    let path = std::path::Path::new(path.trim_start_matches('/'));
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_)))
+        .then(|| std::path::Path::new(SNIPPETS_DIRECTORY).join(path))
+}
+
+/// Render an output snippet as an SVG, without its metadata header. The width of the terminal and
+/// the title of the window default to the width the output was captured in and the command that
+/// was run, falling back to the name of the file.
+fn render_svg(
+    content: &[u8],
+    query: SvgQuery,
+    file_name: Option<String>,
+    palette: &Palette,
+) -> Result<String> {
+    let (metadata, output) = split_metadata(content)?;
+    let metadata = metadata.unwrap_or_default();
+
+    let title = query
+        .title
+        .or_else(|| match (&metadata.cwd, &metadata.command) {
+            (Some(cwd), Some(command)) => Some(format!("{cwd}: {command}")),
+            (Some(location), None) | (None, Some(location)) => Some(location.clone()),
+            (None, None) => file_name,
+        });
+    let options = SvgOptions {
+        palette: palette.clone(),
+        window_chrome: query.chrome.unwrap_or(true),
+        title,
+        terminal_width: query.columns.or_else(|| metadata.columns.map(usize::from)),
+        terminal_height: metadata.rows.map(usize::from),
+        ..Default::default()
+    };
+    Ok(rewrite_ansi_to_svg(
+        &String::from_utf8_lossy(output),
+        &options,
+    ))
+}
+
+/// Serve an output snippet in another format, chosen by an extension appended to the name of the
+/// snippet, such as `/snippets/post/output.txt.svg`.
+#[tracing::instrument(skip(state))]
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+pub(crate) async fn snippet(
+    Path(path): Path<String>,
+    Query(query): Query<SvgQuery>,
+    state: Extension<Arc<State>>,
+) -> Result<Response> {
+    let Some(source) = path.strip_suffix(".svg") else {
+        return Err(Error::SnippetNotFound(path));
+    };
+    let Some(file_path) = snippet_path(source) else {
+        return Err(Error::SnippetNotFound(path));
+    };
+
+    debug!(?file_path, "loading output snippet");
+    let Ok(content) = tokio::fs::read(&file_path).await else {
+        return Err(Error::SnippetNotFound(path));
+    };
+
+    let file_name = file_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string());
+    let svg = render_svg(&content, query, file_name, &state.palette)?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(
+            axum::http::header::CONTENT_TYPE,
+            axum::http::HeaderValue::from_static("image/svg+xml"),
+        )
+        .body(boxed(Full::from(svg)))
+        .expect("unable to serve output snippet"))
+}
+
+/// Download an output snippet of a post, without its metadata header, such as
+/// `/posts/my-post/snippets/output.txt?format=plain`.
+#[tracing::instrument(skip(state))]
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+pub(crate) async fn download(
+    Path((post, path)): Path<(String, String)>,
+    Query(query): Query<DownloadQuery>,
+    state: Extension<Arc<State>>,
+) -> Result<Response> {
+    if !state.posts.contains_key(&post) {
+        return Err(Error::PostNotFound(post));
+    }
+    let Some(file_path) = snippet_path(&format!("{post}/{}", path.trim_start_matches('/'))) else {
+        return Err(Error::SnippetNotFound(path));
+    };
+
+    debug!(?file_path, "loading output snippet");
+    let Ok(content) = tokio::fs::read(&file_path).await else {
+        return Err(Error::SnippetNotFound(path));
+    };
+    let (metadata, output) = split_metadata(&content)?;
+
+    let name = file_path
+        .file_name()
+        .map(|name| name.to_string_lossy().replace('"', "_"))
+        .unwrap_or_default();
+    let (content_type, file_name, body) = match query.format {
+        DownloadFormat::Raw => ("application/octet-stream", name, output.to_vec()),
+        DownloadFormat::Plain => {
+            let options = RenderOptions {
+                emulate_terminal: true,
+                terminal_height: metadata.and_then(|metadata| metadata.rows).map(usize::from),
+                ..Default::default()
+            };
+            let spans = parse_styled_with(&String::from_utf8_lossy(output), &options);
+            let stem = name
+                .rsplit_once('.')
+                .map_or(name.as_str(), |(stem, _)| stem);
+            (
+                "text/plain; charset=utf-8",
+                format!("{stem}.plain.txt"),
+                PlainTextRenderer.render(&spans).into_bytes(),
+            )
+        }
+    };
+    let disposition =
+        axum::http::HeaderValue::from_str(&format!("attachment; filename=\"{file_name}\""))
+            .map_err(|error| Error::InternalServerError(error.to_string()))?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(
+            axum::http::header::CONTENT_TYPE,
+            axum::http::HeaderValue::from_static(content_type),
+        )
+        .header(axum::http::header::CONTENT_DISPOSITION, disposition)
+        .body(boxed(Full::from(body)))
+        .expect("unable to serve output snippet"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolving_snippet_paths() {
+        assert_eq!(
+            snippet_path("/post/output.txt"),
+            Some(PathBuf::from("output_snippets/post/output.txt"))
+        );
+        assert_eq!(snippet_path("post/../../config.yaml"), None);
+        assert_eq!(snippet_path("./output.txt"), None);
+    }
+
+    #[test]
+    fn rendering_snippets_with_metadata() {
+        let content = b"---\ncommand: cargo build\ncwd: ~/opaque\ncolumns: 100\n---\nCompiling\n";
+        let svg = render_svg(
+            content,
+            SvgQuery::default(),
+            Some("build.txt".to_string()),
+            &Palette::default(),
+        )
+        .unwrap();
+        assert!(svg.contains("~/opaque: cargo build"));
+        assert!(svg.contains("Compiling"));
+        assert!(!svg.contains("---"));
+        assert!(!svg.contains("columns"));
+        assert!(!svg.contains("build.txt"));
+
+        let svg = render_svg(
+            b"Compiling\n",
+            SvgQuery::default(),
+            Some("build.txt".to_string()),
+            &Palette::default(),
+        )
+        .unwrap();
+        assert!(svg.contains("build.txt"));
+    }
+}
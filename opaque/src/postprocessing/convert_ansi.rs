@@ -1,21 +1,54 @@
-use color_eyre::eyre::Result;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
 use std::sync::OnceLock;
+
+use color_eyre::eyre::Result;
+use lol_html::html_content::Element;
+use maud::{html, PreEscaped};
+use opaque_ansi::asciicast::{AnimationOptions, Recording};
+use opaque_ansi::palette::Palette;
+use opaque_ansi::render::{PlainTextRenderer, Renderer};
+use opaque_ansi::{
+    parse_styled_with, AnsiHtmlWriter, ColorAdjustment, FileLinkPolicy, RenderOptions,
+};
 use parking_lot::Mutex;
-use std::path::PathBuf;
+use regex::Regex;
 use tracing::{debug, span, Level};
 
-use opaque_ansi::rewrite_ansi_to_html;
+use crate::snippet::{split_metadata, SnippetMetadata};
 
 static CACHE: OnceLock<Mutex<uluru::LRUCache<(String, String), 256>>> = OnceLock::new();
 
+/// Replaces `<opaque-ansi-output>` elements with the rendered contents of the file given by the
+/// `source` attribute, relative to the source directory, or to the page's own directory with the
+/// `relative` attribute. Other attributes change how the output is rendered:
+///
+/// * `terminal` replays the output into a virtual terminal, `columns` sets its width, and `rows`
+///   sets its height, defaulting to the height recorded in a snippet.
+/// * `lines`, `linenos`, `highlight`, `fold`, and `fold-matching` select, number, highlight, and
+///   collapse lines.
+/// * `contrast-background` and `min-contrast`, or `quantize`, adjust colors.
+///
+/// Asciicast recordings, with a `.cast` extension, render a single frame: the final frame, or the
+/// frame at the time in seconds given by `frame-time`. With `animate`, every frame is rendered
+/// instead, played at the speed given by `speed`, with `frame-interval` setting the shortest time
+/// in seconds between frames, as [`AnimationOptions::frame_time`].
 #[derive(Debug, Clone)]
 pub(crate) struct ConvertAnsi {
     source_directory: PathBuf,
     subdirectory: PathBuf,
+    palette: Palette,
 }
 
 impl ConvertAnsi {
-    pub(crate) fn new(source_file_path: String, subdirectory: String) -> Result<Self> {
+    pub(crate) fn new(
+        source_file_path: String,
+        subdirectory: String,
+        palette: Palette,
+    ) -> Result<Self> {
         if CACHE.get().is_none() {
             CACHE
                 .set(Mutex::new(uluru::LRUCache::default()))
@@ -26,10 +59,113 @@ impl ConvertAnsi {
         Ok(ConvertAnsi {
             source_directory,
             subdirectory: PathBuf::from(subdirectory),
+            palette,
         })
     }
 }
 
+impl ConvertAnsi {
+    /// Determine how colors are adjusted from the `contrast-background` and `min-contrast`
+    /// attributes, or the `quantize` attribute.
+    fn color_adjustment(&self, el: &Element) -> Result<ColorAdjustment> {
+        if let Some(background) = el.get_attribute("contrast-background") {
+            return Ok(ColorAdjustment::Contrast {
+                background: background.parse()?,
+                palette: self.palette.clone(),
+                minimum_ratio: el
+                    .get_attribute("min-contrast")
+                    .map(|ratio| ratio.parse())
+                    .transpose()?
+                    .unwrap_or(4.5),
+            });
+        }
+        if el.get_attribute("quantize").is_some() {
+            return Ok(ColorAdjustment::Quantize {
+                palette: self.palette.clone(),
+            });
+        }
+        Ok(ColorAdjustment::None)
+    }
+}
+
+/// Parse a line number or a range of line numbers, such as `5-7`. A range without an end, such as
+/// `120-`, continues until the last line.
+fn parse_line_range(input: &str) -> Result<RangeInclusive<usize>> {
+    Ok(match input.split_once('-') {
+        Some((start, "")) => start.trim().parse()?..=usize::MAX,
+        Some((start, end)) => start.trim().parse()?..=end.trim().parse()?,
+        None => {
+            let line = input.trim().parse()?;
+            line..=line
+        }
+    })
+}
+
+/// Parse a comma-separated list of line numbers and ranges of line numbers, such as `3,5-7`.
+fn parse_line_ranges(input: &str) -> Result<Vec<RangeInclusive<usize>>> {
+    input
+        .split(',')
+        .filter(|range| !range.trim().is_empty())
+        .map(parse_line_range)
+        .collect()
+}
+
+/// Returns the runs of consecutive lines matching the pattern, with lines numbered from 1.
+fn matching_lines(text: &str, pattern: &Regex) -> Vec<RangeInclusive<usize>> {
+    let mut ranges: Vec<RangeInclusive<usize>> = vec![];
+    for (i, line) in text.lines().enumerate() {
+        if !pattern.is_match(line) {
+            continue;
+        }
+        let line = i + 1;
+        match ranges.last_mut() {
+            Some(range) if *range.end() + 1 == line => *range = *range.start()..=line,
+            _ => ranges.push(line..=line),
+        }
+    }
+    ranges
+}
+
+/// Render an output snippet as a terminal window, with a title bar showing the working directory
+/// and exit code, and a prompt line showing the command. Links to download the snippet are added to
+/// the title bar when a download URL is given.
+fn render_window(metadata: &SnippetMetadata, output: &str, download_url: Option<&str>) -> String {
+    html! {
+        .ansi_window {
+            .ansi_window_title {
+                span.ansi_window_buttons { span {} span {} span {} }
+                span.ansi_window_cwd { (metadata.cwd.as_deref().unwrap_or_default()) }
+                @if let Some(exit_code) = metadata.exit_code {
+                    @let status = if exit_code == 0 {
+                        "ansi_exit_success"
+                    } else {
+                        "ansi_exit_failure"
+                    };
+                    span class=(format!("ansi_exit {status}")) title="Exit status" {
+                        "exit " (exit_code)
+                    }
+                }
+                @if let Some(url) = download_url {
+                    span.ansi_window_downloads {
+                        a href=(url) download { "raw" }
+                        " "
+                        a href=(format!("{url}?format=plain")) download { "text" }
+                    }
+                }
+            }
+            @if let Some(command) = &metadata.command {
+                .ansi_prompt_line {
+                    span.ansi_prompt { (metadata.prompt.as_deref().unwrap_or("$")) }
+                    " "
+                    span.ansi_command { (command) }
+                }
+            }
+            (PreEscaped(output))
+        }
+    }
+    .into_string()
+}
+
 impl super::PostProcessor for ConvertAnsi {
     fn build(self) -> super::Closure {
         Box::new(move |el| {
@@ -47,21 +183,120 @@ impl super::PostProcessor for ConvertAnsi {
 
             debug!(?path, "loading ANSI output file");
 
+            // Rendering depends on the resolved path and every attribute, not just the source file
+            let cache_key = std::iter::once(format!("{}", path.display()))
+                .chain(
+                    el.attributes()
+                        .iter()
+                        .map(|attribute| format!("{}={:?}", attribute.name(), attribute.value())),
+                )
+                .collect::<Vec<_>>()
+                .join(" ");
+
             // Return auto generated output from the cache if available
             if let Some(cache_mutex) = CACHE.get() {
                 let mut cache = cache_mutex.lock();
-                if let Some((_, hit)) = cache.find(|(k, _)| filename == *k) {
-                    debug!(?filename, "cache hit");
+                if let Some((_, hit)) = cache.find(|(k, _)| cache_key == *k) {
+                    debug!(?cache_key, "cache hit");
                     el.replace(hit.as_str(), lol_html::html_content::ContentType::Html);
                     return Ok(());
                 }
             }
 
+            let mut options = RenderOptions {
+                emulate_terminal: el.get_attribute("terminal").is_some(),
+                terminal_width: el
+                    .get_attribute("columns")
+                    .map(|columns| columns.parse())
+                    .transpose()?,
+                terminal_height: el
+                    .get_attribute("rows")
+                    .map(|rows| rows.parse())
+                    .transpose()?,
+                // Links to files on the machine the output was captured on are never useful
+                file_links: FileLinkPolicy::Drop,
+                compact: true,
+                color_adjustment: self.color_adjustment(el)?,
+                lines: el
+                    .get_attribute("lines")
+                    .map(|lines| parse_line_range(&lines))
+                    .transpose()?,
+                line_numbers: el.get_attribute("linenos").is_some(),
+                highlight_lines: el
+                    .get_attribute("highlight")
+                    .map(|lines| parse_line_ranges(&lines))
+                    .transpose()?
+                    .unwrap_or_default(),
+                fold_lines: el
+                    .get_attribute("fold")
+                    .map(|lines| parse_line_ranges(&lines))
+                    .transpose()?
+                    .unwrap_or_default(),
+                ..Default::default()
+            };
+            debug!(?options, "formatting file");
             // Note: a leading slash *replaces* the PathBuf, this MUST NOT happen
-            let file = std::fs::File::open(path)?;
-            let file_content = std::io::read_to_string(file)?;
-            debug!("formatting file");
-            let html_output = rewrite_ansi_to_html(file_content.as_str());
+            let html_output = if filename.ends_with(".cast") {
+                let recording = Recording::parse(std::fs::read_to_string(path)?.as_str())?;
+                if el.get_attribute("animate").is_some() {
+                    // Keyframe names must be unique within the page
+                    let mut hasher = DefaultHasher::new();
+                    cache_key.hash(&mut hasher);
+                    let mut animation_options = AnimationOptions {
+                        render: options,
+                        speed: el
+                            .get_attribute("speed")
+                            .map(|speed| speed.parse())
+                            .transpose()?
+                            .unwrap_or(1.0),
+                        id: format!("ansi-cast-{:x}", hasher.finish()),
+                        ..Default::default()
+                    };
+                    if let Some(interval) = el.get_attribute("frame-interval") {
+                        animation_options.frame_time = interval.parse()?;
+                    }
+                    recording.render_animation(&animation_options)
+                } else {
+                    let time = el
+                        .get_attribute("frame-time")
+                        .map(|time| time.parse())
+                        .transpose()?
+                        .unwrap_or(f64::INFINITY);
+                    recording.render_frame(time, &options)
+                }
+            } else {
+                let content = std::fs::read(path)?;
+                let (metadata, content) = split_metadata(&content)?;
+                if let Some(metadata) = &metadata {
+                    options.terminal_height =
+                        options.terminal_height.or(metadata.rows.map(usize::from));
+                }
+                if let Some(pattern) = el.get_attribute("fold-matching") {
+                    // Match against the text as displayed, without any escape sequences
+                    let pattern = Regex::new(&pattern)?;
+                    let spans = parse_styled_with(&String::from_utf8_lossy(content), &options);
+                    let text = PlainTextRenderer.render(&spans);
+                    options.fold_lines.extend(matching_lines(&text, &pattern));
+                }
+                let mut writer = AnsiHtmlWriter::new(vec![], options);
+                writer.write_all(content)?;
+                let html_output = String::from_utf8(writer.finish()?)?;
+                match metadata {
+                    Some(metadata) => {
+                        // Downloads are served beside the post, so only relative snippets can be
+                        // linked to
+                        let download_url = el.get_attribute("relative").map(|_| {
+                            format!(
+                                "/posts/{}/snippets/{}",
+                                self.subdirectory.display(),
+                                filename.trim_matches('/')
+                            )
+                        });
+                        render_window(&metadata, &html_output, download_url.as_deref())
+                    }
+                    None => html_output,
+                }
+            };
             el.replace(
                 html_output.as_str(),
                 lol_html::html_content::ContentType::Html,
@@ -69,9 +304,9 @@ impl super::PostProcessor for ConvertAnsi {
 
             if let Some(cache_mutex) = CACHE.get() {
                 let mut cache = cache_mutex.lock();
-                if cache.find(|(k, _)| filename == *k).is_none() {
-                    debug!(?filename, "cache miss, updating");
-                    cache.insert((filename, html_output));
+                if cache.find(|(k, _)| cache_key == *k).is_none() {
+                    debug!(?cache_key, "cache miss, updating");
+                    cache.insert((cache_key, html_output));
                 }
             }
 
@@ -79,3 +314,44 @@ impl super::PostProcessor for ConvertAnsi {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing_line_ranges() {
+        assert_eq!(parse_line_range("120-140").unwrap(), 120..=140);
+        assert_eq!(parse_line_range("120-").unwrap(), 120..=usize::MAX);
+        assert_eq!(parse_line_ranges("3, 5-7,").unwrap(), vec![3..=3, 5..=7]);
+        assert!(parse_line_ranges("3,x").is_err());
+    }
+
+    #[test]
+    fn rendering_windows() {
+        let metadata = SnippetMetadata {
+            command: Some("cargo run <x>".to_string()),
+            cwd: Some("~/opaque".to_string()),
+            exit_code: Some(101),
+            ..Default::default()
+        };
+        let window = render_window(&metadata, "<pre></pre>", Some("/posts/a/snippets/b.txt"));
+        assert!(window.contains("<span class=\"ansi_window_cwd\">~/opaque</span>"));
+        assert!(window.contains(
+            "<span class=\"ansi_exit ansi_exit_failure\" title=\"Exit status\">exit 101</span>"
+        ));
+        assert!(window.contains(
+            "<span class=\"ansi_prompt\">$</span> \
+             <span class=\"ansi_command\">cargo run &lt;x&gt;</span>"
+        ));
+        assert!(window.contains("<a href=\"/posts/a/snippets/b.txt?format=plain\" download>"));
+        assert!(window.ends_with("<pre></pre></div>"));
+    }
+
+    #[test]
+    fn matching_lines_to_fold() {
+        let text = "   Compiling a\n   Compiling b\nwarning: c\n   Compiling d\n";
+        let pattern = Regex::new("^ +Compiling").unwrap();
+        assert_eq!(matching_lines(text, &pattern), vec![1..=2, 4..=4]);
+    }
+}
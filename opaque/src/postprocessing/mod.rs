@@ -1,5 +1,6 @@
 use color_eyre::eyre::{eyre, Result};
 use lol_html::{element, RewriteStrSettings};
+use opaque_ansi::palette::Palette;
 
 mod rewrite_links;
 use rewrite_links::RewriteLinks;
@@ -50,9 +51,12 @@ impl PostProcessingBuilder {
         selector: String,
         source_file_path: String,
         subdirectory: String,
+        palette: Palette,
     ) -> Result<Self> {
-        self.convert_ansi_selector
-            .push((selector, ConvertAnsi::new(source_file_path, subdirectory)?));
+        self.convert_ansi_selector.push((
+            selector,
+            ConvertAnsi::new(source_file_path, subdirectory, palette)?,
+        ));
         Ok(self)
     }
 
@@ -0,0 +1,105 @@
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{Result, WrapErr};
+use serde::{Deserialize, Serialize};
+
+/// The directory containing output snippets, as used by `<opaque-ansi-output>` elements.
+pub(crate) static SNIPPETS_DIRECTORY: &str = "output_snippets";
+
+/// Details of the command an output snippet was captured from, stored as YAML between two `---`
+/// lines at the start of the snippet, in the same way as the front matter of a post.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct SnippetMetadata {
+    /// The command line that was run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) command: Option<String>,
+
+    /// The working directory the command was run in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) cwd: Option<String>,
+
+    /// The prompt shown before the command, defaulting to `$`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) prompt: Option<String>,
+
+    /// The exit code of the command
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) exit_code: Option<i32>,
+
+    /// When the output was captured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) captured_at: Option<DateTime<Utc>>,
+
+    /// The width of the terminal the output was captured in, in columns
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) columns: Option<u16>,
+
+    /// The height of the terminal the output was captured in, in rows
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) rows: Option<u16>,
+}
+
+/// Split the metadata header from the start of a snippet, returning the metadata, if the snippet
+/// has a header, and the captured output following it.
+pub(crate) fn split_metadata(content: &[u8]) -> Result<(Option<SnippetMetadata>, &[u8])> {
+    let Some(rest) = content.strip_prefix(b"---\n") else {
+        return Ok((None, content));
+    };
+
+    let mut header_length = 0;
+    for line in rest.split_inclusive(|byte| *byte == b'\n') {
+        if line == b"---\n" || line == b"---" {
+            let header = std::str::from_utf8(&rest[..header_length])?;
+            // An empty document isn't a valid mapping, but is a valid header
+            let metadata = if header.trim().is_empty() {
+                SnippetMetadata::default()
+            } else {
+                serde_yaml::from_str(header)?
+            };
+            return Ok((Some(metadata), &rest[header_length + line.len()..]));
+        }
+        header_length += line.len();
+    }
+
+    // Without a closing line, the output just happens to start with `---`
+    Ok((None, content))
+}
+
+/// Write a snippet with a metadata header, creating any missing parent directories.
+pub(crate) fn write_snippet(path: &Path, metadata: &SnippetMetadata, output: &[u8]) -> Result<()> {
+    let mut snippet = format!("---\n{}---\n", serde_yaml::to_string(metadata)?).into_bytes();
+    snippet.extend_from_slice(output);
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, snippet).wrap_err_with(|| format!("Unable to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splitting_metadata() {
+        let content = b"---\ncommand: cargo run\nexit_code: 101\n---\n\x1b[1merror\x1b[0m\n";
+        let (metadata, output) = split_metadata(content).unwrap();
+        assert_eq!(
+            metadata,
+            Some(SnippetMetadata {
+                command: Some("cargo run".to_string()),
+                exit_code: Some(101),
+                ..Default::default()
+            })
+        );
+        assert_eq!(output, b"\x1b[1merror\x1b[0m\n");
+
+        let (metadata, output) = split_metadata(b"---\n---\nok").unwrap();
+        assert_eq!(metadata, Some(SnippetMetadata::default()));
+        assert_eq!(output, b"ok");
+
+        let content = b"---\nnot a header\n";
+        assert_eq!(split_metadata(content).unwrap(), (None, &content[..]));
+    }
+}
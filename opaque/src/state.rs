@@ -3,6 +3,8 @@ use std::{collections::HashMap, path::PathBuf};
 use chrono::{DateTime, Utc};
 use clap::Parser;
 use color_eyre::eyre::{Report, Result};
+use opaque_ansi::palette::{Palette, PaletteError, Theme};
+use opaque_markdown::{MarkdownOptions, SyntaxOptions, DEFAULT_DARK_THEME, DEFAULT_LIGHT_THEME};
 use serde::{Deserialize, Serialize};
 use tokio::fs::read_to_string;
 
@@ -29,6 +31,50 @@ impl std::str::FromStr for Author {
     }
 }
 
+/// Markdown extensions to enable or disable, overriding the defaults of [`MarkdownOptions`]. Set
+/// for the whole blog in the configuration file, and for a single post in its front matter.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(crate) struct MarkdownConfig {
+    pub(crate) strikethrough: Option<bool>,
+    pub(crate) tables: Option<bool>,
+    pub(crate) autolinks: Option<bool>,
+    pub(crate) task_lists: Option<bool>,
+    pub(crate) description_lists: Option<bool>,
+    pub(crate) footnotes: Option<bool>,
+    pub(crate) superscript: Option<bool>,
+    pub(crate) smart_punctuation: Option<bool>,
+    pub(crate) hard_breaks: Option<bool>,
+    /// The prefix of the IDs given to headers; an empty prefix doesn't give headers IDs
+    pub(crate) header_id_prefix: Option<String>,
+    pub(crate) unsafe_html: Option<bool>,
+}
+
+impl MarkdownConfig {
+    /// Override any options that have been set.
+    pub(crate) fn apply(&self, options: &mut MarkdownOptions) {
+        let flags = [
+            (self.strikethrough, &mut options.strikethrough),
+            (self.tables, &mut options.tables),
+            (self.autolinks, &mut options.autolinks),
+            (self.task_lists, &mut options.task_lists),
+            (self.description_lists, &mut options.description_lists),
+            (self.footnotes, &mut options.footnotes),
+            (self.superscript, &mut options.superscript),
+            (self.smart_punctuation, &mut options.smart_punctuation),
+            (self.hard_breaks, &mut options.hard_breaks),
+            (self.unsafe_html, &mut options.unsafe_html),
+        ];
+        for (value, option) in flags {
+            if let Some(value) = value {
+                *option = value;
+            }
+        }
+        if let Some(prefix) = &self.header_id_prefix {
+            options.header_id_prefix = (!prefix.is_empty()).then(|| prefix.clone());
+        }
+    }
+}
+
 #[derive(Clone, Deserialize, Serialize)]
 pub(crate) struct FrontMatter {
     #[serde(default)]
@@ -36,6 +82,7 @@ pub(crate) struct FrontMatter {
     pub(crate) author: Option<Author>,
     pub(crate) date: Option<DateTime<Utc>>,
     pub(crate) published: Option<bool>,
+    pub(crate) markdown: Option<MarkdownConfig>,
 }
 
 impl FrontMatter {
@@ -60,10 +107,82 @@ pub(crate) struct Config {
     pub(crate) url: String,
     pub(crate) static_path: PathBuf,
     pub(crate) bind_address: std::net::SocketAddr,
+    pub(crate) terminal_theme: Option<String>,
+    pub(crate) terminal_palette: Option<PathBuf>,
+    pub(crate) syntax_dump: Option<PathBuf>,
+    pub(crate) syntax_directory: Option<PathBuf>,
+    pub(crate) syntax_aliases: Option<HashMap<String, String>>,
+    pub(crate) syntax_theme_light: Option<String>,
+    pub(crate) syntax_theme_dark: Option<String>,
+    pub(crate) markdown: Option<MarkdownConfig>,
+}
+
+impl Config {
+    /// Returns where the syntax definitions used to highlight code blocks are loaded from.
+    pub(crate) fn syntax_options(&self) -> SyntaxOptions {
+        SyntaxOptions {
+            dump: self.syntax_dump.clone(),
+            directory: self.syntax_directory.clone(),
+            aliases: self.syntax_aliases.clone().unwrap_or_default(),
+        }
+    }
+
+    /// Generate the stylesheet for highlighted code blocks from the configured light and dark
+    /// themes, or the default themes if they aren't given.
+    pub(crate) fn syntax_stylesheet(&self) -> Result<String> {
+        opaque_markdown::syntax_stylesheet(
+            self.syntax_theme_light
+                .as_deref()
+                .unwrap_or(DEFAULT_LIGHT_THEME),
+            self.syntax_theme_dark
+                .as_deref()
+                .unwrap_or(DEFAULT_DARK_THEME),
+        )
+    }
+
+    /// Returns the options used to render a page, applying the Markdown settings of the
+    /// configuration file and then those of the front matter of the page.
+    pub(crate) fn markdown_options(&self, front_matter: Option<&FrontMatter>) -> MarkdownOptions {
+        let mut options = MarkdownOptions::default();
+        let overrides = [
+            self.markdown.as_ref(),
+            front_matter.and_then(|front_matter| front_matter.markdown.as_ref()),
+        ];
+        for config in overrides.into_iter().flatten() {
+            config.apply(&mut options);
+        }
+        options
+    }
+
+    /// Load the terminal color palette from the configured palette file, or the configured
+    /// built-in theme if no palette file is given.
+    pub(crate) fn palette(&self) -> Result<Palette, PaletteError> {
+        if let Some(path) = &self.terminal_palette {
+            return Palette::from_file(path);
+        }
+        match &self.terminal_theme {
+            Some(theme) => Ok(theme.parse::<Theme>()?.palette()),
+            None => Ok(Palette::default()),
+        }
+    }
+
+    /// Generate the stylesheet served as `/terminal.css`. Without a configured theme or palette
+    /// file, the 16 base colors are left to the site theme in main.css, which has a variant for
+    /// dark mode, and only the extended colors are defined.
+    pub(crate) fn terminal_stylesheet(&self, palette: &Palette) -> String {
+        if self.terminal_theme.is_some() || self.terminal_palette.is_some() {
+            palette.stylesheet()
+        } else {
+            palette.extended_stylesheet()
+        }
+    }
 }
 
 pub(crate) struct State {
     pub(crate) config: Config,
+    pub(crate) palette: Palette,
+    pub(crate) terminal_stylesheet: String,
+    pub(crate) syntax_stylesheet: String,
     pub(crate) page_map: Vec<(String, String)>,
     pub(crate) posts: PageMap,
 }
@@ -85,7 +204,22 @@ impl State {
                 bind_address: "0.0.0.0:8000"
                     .parse()
                     .expect("couldn't parse static address"),
+                terminal_theme: None,
+                terminal_palette: None,
+                syntax_dump: None,
+                syntax_directory: None,
+                syntax_aliases: None,
+                syntax_theme_light: None,
+                syntax_theme_dark: None,
+                markdown: None,
             },
+            palette: Palette::default(),
+            terminal_stylesheet: Palette::default().extended_stylesheet(),
+            syntax_stylesheet: opaque_markdown::syntax_stylesheet(
+                DEFAULT_LIGHT_THEME,
+                DEFAULT_DARK_THEME,
+            )
+            .expect("couldn't generate default syntax stylesheet"),
             page_map: vec![],
             posts: HashMap::new(),
         }
@@ -107,10 +241,16 @@ impl State {
 
         // convert from a PartialConfig to a Config, turning Option<T> into T
         let config: Config = serde_yaml::from_value(serde_yaml::to_value(config_object)?)?;
+        let palette = config.palette()?;
+        let terminal_stylesheet = config.terminal_stylesheet(&palette);
+        let syntax_stylesheet = config.syntax_stylesheet()?;
 
         Ok(State {
             // serialize to value, deserialize from value
             config,
+            palette,
+            terminal_stylesheet,
+            syntax_stylesheet,
             page_map: vec![],
             posts: HashMap::new(),
         })
@@ -164,8 +304,61 @@ mod tests {
                 author: None,
                 date: None,
                 published: None,
+                markdown: None,
             };
             assert_eq!(fm.slug(), actual);
         }
     }
+
+    #[test]
+    fn layer_markdown_options() {
+        let mut config: Config = serde_yaml::from_str(
+            "name: Enigma\n\
+             description: ''\n\
+             author: { name: Ryan Heywood, email: me@ryansquared.pub }\n\
+             url: http://localhost:8000\n\
+             static_path: static\n\
+             bind_address: 127.0.0.1:8000\n\
+             markdown: { footnotes: true, header_id_prefix: '' }\n",
+        )
+        .unwrap();
+        let front_matter: FrontMatter = serde_yaml::from_str(
+            "title: Footnotes\nmarkdown: { footnotes: false, hard_breaks: true }\n",
+        )
+        .unwrap();
+
+        let options = config.markdown_options(None);
+        assert!(options.footnotes);
+        assert_eq!(options.header_id_prefix, None);
+        assert!(options.strikethrough);
+
+        let options = config.markdown_options(Some(&front_matter));
+        assert!(!options.footnotes);
+        assert!(options.hard_breaks);
+
+        config.markdown = None;
+        assert_eq!(config.markdown_options(None), MarkdownOptions::default());
+    }
+
+    #[test]
+    fn generate_terminal_stylesheet() {
+        let mut config: Config = serde_yaml::from_str(
+            "name: Enigma\n\
+             description: ''\n\
+             author: { name: Ryan Heywood, email: me@ryansquared.pub }\n\
+             url: http://localhost:8000\n\
+             static_path: static\n\
+             bind_address: 127.0.0.1:8000\n",
+        )
+        .unwrap();
+        let default = config.terminal_stylesheet(&config.palette().unwrap());
+        assert!(!default.contains("--terminal-color-1:"));
+        assert!(default.contains("--terminal-color-16:"));
+
+        config.terminal_theme = Some("vga".to_string());
+        let themed = config.terminal_stylesheet(&config.palette().unwrap());
+        assert_ne!(themed, default);
+        assert!(themed.contains("\t--terminal-color-1: #aa0000;"));
+        assert!(themed.contains("--terminal-color-255:"));
+    }
 }
@@ -0,0 +1,462 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use chrono::{DateTime, Utc};
+use clap::Args;
+use color_eyre::eyre::{bail, Result, WrapErr};
+use regex::Regex;
+use tracing::{debug, info, warn};
+use walkdir::WalkDir;
+
+use crate::capture::{exit_code, run_in_pty};
+use crate::snippet::{split_metadata, write_snippet, SNIPPETS_DIRECTORY};
+
+/// Patterns matching output that changes between runs, and what they're replaced with, which are
+/// applied before any normalizations given on the command line.
+static DEFAULT_NORMALIZATIONS: &[(&str, &str)] = &[
+    // Timings, such as `Finished dev [unoptimized + debuginfo] target(s) in 1.23s`
+    (r"\b\d+(?:\.\d+)?(?:ns|µs|us|ms|s)\b", "[duration]"),
+    // Version numbers, such as `Compiling libc v0.2.137`
+    (r"\bv?\d+\.\d+\.\d+(?:-[0-9A-Za-z.-]+)?\b", "[version]"),
+];
+
+/// The number of unchanged lines shown around every changed line of a diff.
+const CONTEXT_LINES: usize = 3;
+
+/// A pattern matching output that changes between runs, and what it's replaced with.
+#[derive(Clone, Debug)]
+pub(crate) struct Normalization {
+    pattern: Regex,
+    replacement: String,
+}
+
+impl std::str::FromStr for Normalization {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // A pattern can match `=` using `\x3D`, but the replacement can't be escaped
+        let (pattern, replacement) = s
+            .split_once('=')
+            .ok_or("missing = between the pattern and the replacement")?;
+        Ok(Normalization {
+            pattern: Regex::new(pattern).map_err(|error| error.to_string())?,
+            replacement: replacement.to_string(),
+        })
+    }
+}
+
+/// Re-run the commands recorded in output snippets, and compare the output to the stored output
+#[derive(Clone, Debug, Args)]
+pub(crate) struct VerifyArgs {
+    /// The snippets to verify, defaulting to every snippet with a recorded command
+    pub(crate) paths: Vec<PathBuf>,
+
+    /// Rewrite snippets whose output changed with the new output
+    #[arg(long)]
+    pub(crate) update: bool,
+
+    /// A directory copied into the sandbox commands run in, defaulting to the recorded working
+    /// directory if it exists. Snippets recorded in the same directory share a sandbox
+    #[arg(long)]
+    pub(crate) source: Option<PathBuf>,
+
+    /// A regex matching output that changes between runs, and what it's replaced with before
+    /// comparing output, which may be given more than once. The regex ends at the first `=`
+    #[arg(long = "normalize", value_name = "PATTERN=REPLACEMENT")]
+    pub(crate) normalizations: Vec<Normalization>,
+}
+
+/// A line of a diff between the stored output and the new output.
+#[derive(Debug, PartialEq, Eq)]
+enum Change<'a> {
+    Equal(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Find the changes from the old lines to the new lines, using the longest common subsequence of
+/// the lines. Snippets are short enough that the quadratic time this takes doesn't matter.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Change<'a>> {
+    // lengths[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut lengths = vec![vec![0_usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut changes = vec![];
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            changes.push(Change::Equal(old[i]));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            changes.push(Change::Removed(old[i]));
+            i += 1;
+        } else {
+            changes.push(Change::Added(new[j]));
+            j += 1;
+        }
+    }
+    changes.extend(old[i..].iter().map(|line| Change::Removed(line)));
+    changes.extend(new[j..].iter().map(|line| Change::Added(line)));
+    changes
+}
+
+/// Render the changed lines of a diff, and the lines around them, for a terminal. Lines keep their
+/// own formatting, with the formatting reset at the end of every line.
+fn render_diff(changes: &[Change]) -> String {
+    let changed = |i: usize| !matches!(changes[i], Change::Equal(_));
+    let near_change = |i: usize| {
+        (i.saturating_sub(CONTEXT_LINES)..(i + CONTEXT_LINES + 1).min(changes.len())).any(changed)
+    };
+
+    let mut output = String::new();
+    let mut skipped = false;
+    for (i, change) in changes.iter().enumerate() {
+        if !near_change(i) {
+            skipped = true;
+            continue;
+        }
+        if skipped {
+            output.push_str("\x1b[2m  …\x1b[0m\n");
+            skipped = false;
+        }
+        let line = match change {
+            Change::Equal(line) => format!("  {line}"),
+            Change::Removed(line) => format!("\x1b[31m-\x1b[0m {line}"),
+            Change::Added(line) => format!("\x1b[32m+\x1b[0m {line}"),
+        };
+        output.push_str(&line);
+        output.push_str("\x1b[0m\n");
+    }
+    if skipped {
+        output.push_str("\x1b[2m  …\x1b[0m\n");
+    }
+    output
+}
+
+/// Replace the parts of an output that change between runs.
+fn normalize(output: &[u8], home: Option<&Path>, normalizations: &[Normalization]) -> String {
+    let mut output = String::from_utf8_lossy(output).replace("\r\n", "\n");
+    if let Some(home) = home.and_then(Path::to_str).filter(|home| !home.is_empty()) {
+        output = output.replace(home, "~");
+    }
+    for Normalization {
+        pattern,
+        replacement,
+    } in normalizations
+    {
+        output = pattern
+            .replace_all(&output, replacement.as_str())
+            .into_owned();
+    }
+    output
+}
+
+/// Expand a directory starting with `~` to be within the home directory.
+fn expand_directory(directory: &str, home: Option<&Path>) -> PathBuf {
+    match (directory.strip_prefix('~'), home) {
+        (Some(relative), Some(home)) => home.join(relative.trim_start_matches('/')),
+        _ => PathBuf::from(directory),
+    }
+}
+
+/// Copy every file within a directory into another directory.
+fn copy_directory(source: &Path, destination: &Path) -> Result<()> {
+    for entry in WalkDir::new(source) {
+        let entry = entry?;
+        let target = destination.join(entry.path().strip_prefix(source)?);
+        if entry.file_type().is_dir() {
+            std::fs::create_dir_all(&target)?;
+        } else if entry.file_type().is_symlink() {
+            std::os::unix::fs::symlink(std::fs::read_link(entry.path())?, &target)?;
+        } else {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Copies of the working directories commands run in, so that they can't change the originals.
+/// Each directory is copied once and shared by every snippet recorded in it, in the order they're
+/// verified, so build output and other files are reused as they were when the snippets were
+/// recorded. The copies are removed when dropped.
+#[derive(Debug, Default)]
+struct Sandboxes {
+    directories: HashMap<Option<PathBuf>, PathBuf>,
+}
+
+impl Sandboxes {
+    /// Returns the sandbox for a source directory, copying the directory the first time it's used.
+    /// Commands without a source directory share an empty sandbox.
+    fn get(&mut self, source: Option<&Path>) -> Result<&Path> {
+        let count = self.directories.len();
+        let entry = match self.directories.entry(source.map(Path::to_path_buf)) {
+            Entry::Occupied(entry) => return Ok(entry.into_mut()),
+            Entry::Vacant(entry) => entry,
+        };
+
+        let sandbox =
+            std::env::temp_dir().join(format!("opaque-verify-{}-{count}", std::process::id()));
+        if sandbox.exists() {
+            std::fs::remove_dir_all(&sandbox)?;
+        }
+        std::fs::create_dir_all(&sandbox)?;
+        if let Some(source) = source {
+            debug!(?source, ?sandbox, "copying working directory into sandbox");
+            copy_directory(source, &sandbox)?;
+        }
+        Ok(entry.insert(sandbox))
+    }
+}
+
+impl Drop for Sandboxes {
+    fn drop(&mut self) {
+        for sandbox in self.directories.values() {
+            if let Err(error) = std::fs::remove_dir_all(sandbox) {
+                warn!(?sandbox, %error, "unable to remove sandbox");
+            }
+        }
+    }
+}
+
+/// Re-run the command recorded in a snippet, returning whether the output is unchanged.
+#[tracing::instrument(skip(args, normalizations, sandboxes))]
+fn verify_snippet(
+    path: &Path,
+    args: &VerifyArgs,
+    normalizations: &[Normalization],
+    home: Option<&Path>,
+    sandboxes: &mut Sandboxes,
+) -> Result<bool> {
+    let content =
+        std::fs::read(path).wrap_err_with(|| format!("Unable to read {}", path.display()))?;
+    let (Some(mut metadata), stored) = split_metadata(&content)? else {
+        warn!(?path, "snippet has no metadata, skipping");
+        return Ok(true);
+    };
+    let Some(command_line) = metadata.command.clone() else {
+        warn!(?path, "snippet has no recorded command, skipping");
+        return Ok(true);
+    };
+    let cwd = metadata
+        .cwd
+        .as_deref()
+        .map(|cwd| expand_directory(cwd, home));
+
+    let source = args
+        .source
+        .clone()
+        .or_else(|| cwd.clone().filter(|cwd| cwd.is_dir()));
+    let sandbox = sandboxes.get(source.as_deref())?.to_path_buf();
+
+    info!(?path, ?command_line, "re-running command");
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(&command_line).current_dir(&sandbox);
+    let (output, status) = run_in_pty(
+        command,
+        metadata.columns.unwrap_or(100),
+        metadata.rows.unwrap_or(30),
+        false,
+    )?;
+
+    // Paths within the sandbox should look like paths within the recorded working directory
+    let fresh = match (sandbox.to_str(), cwd.as_deref().and_then(Path::to_str)) {
+        (Some(sandbox), Some(cwd)) => String::from_utf8_lossy(&output)
+            .replace(sandbox, cwd)
+            .into_bytes(),
+        _ => output,
+    };
+    let (stored_text, fresh_text) = (
+        normalize(stored, home, normalizations),
+        normalize(&fresh, home, normalizations),
+    );
+    let exit_code = exit_code(status);
+    // Snippets may have been written without an exit code, which isn't compared
+    let exit_code_changed = metadata
+        .exit_code
+        .is_some_and(|recorded| recorded != exit_code);
+    if stored_text == fresh_text && !exit_code_changed {
+        eprintln!("\x1b[32mok\x1b[0m {}", path.display());
+        return Ok(true);
+    }
+
+    eprintln!("\x1b[1;31mchanged\x1b[0m {}", path.display());
+    if let (true, Some(recorded)) = (exit_code_changed, metadata.exit_code) {
+        eprintln!("  exit code: {recorded} -> {exit_code}");
+    }
+    let stored_lines: Vec<&str> = stored_text.lines().collect();
+    let fresh_lines: Vec<&str> = fresh_text.lines().collect();
+    eprint!("{}", render_diff(&diff_lines(&stored_lines, &fresh_lines)));
+
+    if args.update {
+        metadata.exit_code = Some(exit_code);
+        metadata.captured_at = Some(DateTime::<Utc>::from(std::time::SystemTime::now()));
+        write_snippet(path, &metadata, &fresh)?;
+        eprintln!("  updated {}", path.display());
+    }
+    Ok(false)
+}
+
+#[tracing::instrument]
+pub(crate) fn verify(args: &VerifyArgs) -> Result<()> {
+    let paths = if args.paths.is_empty() {
+        let mut paths = vec![];
+        for entry in WalkDir::new(SNIPPETS_DIRECTORY).sort_by_file_name() {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            // Only snippets with a recorded command can be verified
+            let content = std::fs::read(entry.path())?;
+            if let (Some(metadata), _) = split_metadata(&content)? {
+                if metadata.command.is_some() {
+                    paths.push(entry.into_path());
+                }
+            }
+        }
+        paths
+    } else {
+        args.paths.clone()
+    };
+
+    let mut normalizations = DEFAULT_NORMALIZATIONS
+        .iter()
+        .map(|(pattern, replacement)| Normalization {
+            pattern: Regex::new(pattern).expect("default normalizations are valid"),
+            replacement: (*replacement).to_string(),
+        })
+        .collect::<Vec<_>>();
+    normalizations.extend(args.normalizations.iter().cloned());
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+
+    let mut sandboxes = Sandboxes::default();
+    let mut changed = 0;
+    for path in &paths {
+        if !verify_snippet(path, args, &normalizations, home.as_deref(), &mut sandboxes)? {
+            changed += 1;
+        }
+    }
+
+    match changed {
+        0 => eprintln!("{} snippets verified", paths.len()),
+        _ if args.update => eprintln!("{changed} of {} snippets updated", paths.len()),
+        _ => bail!(
+            "{changed} of {} snippets changed, run with --update to rewrite them",
+            paths.len()
+        ),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diffing_lines() {
+        let changes = diff_lines(&["a", "b", "c"], &["a", "c", "d"]);
+        assert_eq!(
+            changes,
+            [
+                Change::Equal("a"),
+                Change::Removed("b"),
+                Change::Equal("c"),
+                Change::Added("d"),
+            ]
+        );
+
+        let old: Vec<String> = (0..10).map(|i| i.to_string()).collect();
+        let mut new = old.clone();
+        new[9] = "x".to_string();
+        let old: Vec<&str> = old.iter().map(String::as_str).collect();
+        let new: Vec<&str> = new.iter().map(String::as_str).collect();
+        assert_eq!(
+            render_diff(&diff_lines(&old, &new)),
+            "\x1b[2m  …\x1b[0m\n  6\x1b[0m\n  7\x1b[0m\n  8\x1b[0m\n\
+             \x1b[31m-\x1b[0m 9\x1b[0m\n\x1b[32m+\x1b[0m x\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn normalizing_output() {
+        let normalizations = [
+            DEFAULT_NORMALIZATIONS
+                .iter()
+                .map(|(pattern, replacement)| format!("{pattern}={replacement}"))
+                .collect::<Vec<_>>(),
+            vec![r"#\d+=#N".to_string()],
+        ]
+        .concat()
+        .iter()
+        .map(|normalization| normalization.parse().unwrap())
+        .collect::<Vec<Normalization>>();
+        let output = b"Compiling libc v0.2.137 (/home/ryan/libc)\r\n\
+                       Finished dev target(s) in 12.34s\r\nissue #42\n";
+        assert_eq!(
+            normalize(output, Some(Path::new("/home/ryan")), &normalizations),
+            "Compiling libc [version] (~/libc)\nFinished dev target(s) in [duration]\nissue #N\n"
+        );
+    }
+
+    #[test]
+    fn parsing_normalizations() {
+        let normalization = r"id\x3D\d+=id=[id]".parse::<Normalization>().unwrap();
+        assert_eq!(normalization.pattern.as_str(), r"id\x3D\d+");
+        assert_eq!(normalization.replacement, "id=[id]");
+
+        let normalization = "=?".parse::<Normalization>().unwrap();
+        assert_eq!(normalization.pattern.as_str(), "");
+        assert_eq!(normalization.replacement, "?");
+
+        assert!("[0-9]+".parse::<Normalization>().is_err());
+        assert!("(=x".parse::<Normalization>().is_err());
+    }
+
+    #[test]
+    fn expanding_directories() {
+        let home = Some(Path::new("/home/ryan"));
+        assert_eq!(expand_directory("~", home), PathBuf::from("/home/ryan"));
+        assert_eq!(
+            expand_directory("~/opaque", home),
+            PathBuf::from("/home/ryan/opaque")
+        );
+        assert_eq!(expand_directory("/tmp", home), PathBuf::from("/tmp"));
+    }
+
+    #[test]
+    fn sharing_sandboxes() {
+        let source = std::env::temp_dir().join(format!("opaque-source-{}", std::process::id()));
+        std::fs::create_dir_all(source.join("target")).unwrap();
+        std::fs::write(source.join("target/build"), "cached").unwrap();
+
+        let mut sandboxes = Sandboxes::default();
+        let sandbox = sandboxes.get(Some(&source)).unwrap().to_path_buf();
+        assert_eq!(
+            std::fs::read_to_string(sandbox.join("target/build")).unwrap(),
+            "cached"
+        );
+        std::fs::write(sandbox.join("output"), "").unwrap();
+        std::fs::remove_dir_all(&source).unwrap();
+
+        // The source is only copied once, and changes made by commands are kept
+        assert_eq!(sandboxes.get(Some(&source)).unwrap(), sandbox);
+        assert!(sandbox.join("output").exists());
+        let empty = sandboxes.get(None).unwrap().to_path_buf();
+        assert_ne!(empty, sandbox);
+        assert_eq!(std::fs::read_dir(&empty).unwrap().count(), 0);
+
+        drop(sandboxes);
+        assert!(!sandbox.exists());
+        assert!(!empty.exists());
+    }
+}